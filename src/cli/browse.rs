@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use image::DynamicImage;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::util::preview;
+use crate::util::repo::theme_installed;
+use crate::util::search;
+use crate::util::theme::Theme;
+
+// previews are shrunk to this once they're loaded, the pane is never bigger than a few dozen cells
+const THUMBNAIL_WIDTH: u32 = 160;
+const THUMBNAIL_HEIGHT: u32 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Install,
+    Uninstall,
+    Enable,
+    Disable,
+    Update,
+}
+
+impl Action {
+    pub fn past_tense(&self) -> &'static str {
+        match self {
+            Action::Install => "installed",
+            Action::Uninstall => "uninstalled",
+            Action::Enable => "enabled",
+            Action::Disable => "disabled",
+            Action::Update => "updated",
+        }
+    }
+}
+
+enum Preview {
+    Loading,
+    Ready(DynamicImage),
+    Failed(String),
+}
+
+// state of `hyprtheme browse`, kept between runs so the list looks the same after an action
+pub struct Browser {
+    themes: Vec<Theme>,
+    enabled: Vec<String>,
+    theme_dir: PathBuf,
+    query: String,
+    searching: bool,
+    filtered: Vec<Theme>,
+    list: ListState,
+    message: Option<String>,
+    // asked for before an action that can't be undone from here
+    confirming: Option<(Action, Theme)>,
+    previews: HashMap<String, Preview>,
+    sender: Sender<(String, Result<DynamicImage, String>)>,
+    receiver: Receiver<(String, Result<DynamicImage, String>)>,
+}
+
+fn standardized(theme: &Theme) -> String {
+    theme.name.to_lowercase().replace(" ", "_")
+}
+
+impl Browser {
+    pub fn new(themes: Vec<Theme>, enabled: Vec<String>, theme_dir: PathBuf) -> Browser {
+        let (sender, receiver) = mpsc::channel();
+        let mut browser = Browser {
+            themes,
+            enabled,
+            theme_dir,
+            query: String::new(),
+            searching: true,
+            filtered: Vec::new(),
+            list: ListState::default(),
+            message: None,
+            confirming: None,
+            previews: HashMap::new(),
+            sender,
+            receiver,
+        };
+        browser.filter();
+        browser
+    }
+
+    // after an action changed what's installed or enabled
+    pub fn refresh(&mut self, enabled: Vec<String>) {
+        for theme in self.themes.iter_mut() {
+            theme._installed = Some(theme_installed(&standardized(theme), &self.theme_dir));
+        }
+        self.enabled = enabled;
+        let selected = self.selected().map(|theme| theme.qualified_name());
+        self.filter();
+        if let Some(index) = self.filtered.iter().position(|theme| Some(theme.qualified_name()) == selected) {
+            self.list.select(Some(index));
+        }
+    }
+
+    pub fn set_message(&mut self, message: impl Into<String>) {
+        self.message = Some(message.into());
+    }
+
+    fn filter(&mut self) {
+        self.filtered = search::search(self.themes.clone(), &self.query);
+        self.list.select(match self.filtered.is_empty() {
+            true => None,
+            false => Some(0),
+        });
+    }
+
+    fn selected(&self) -> Option<&Theme> {
+        self.list.selected().and_then(|index| self.filtered.get(index))
+    }
+
+    fn is_enabled(&self, theme: &Theme) -> bool {
+        self.enabled.contains(&standardized(theme))
+    }
+
+    // takes over the terminal until the user quits (None) or picks an action for a theme
+    pub fn run(&mut self) -> Result<Option<(Action, Theme)>, String> {
+        let mut terminal = match ratatui::try_init() {
+            Ok(terminal) => terminal,
+            Err(e) => return Err(format!("Failed to set up the terminal: {}", e)),
+        };
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<Option<(Action, Theme)>, String> {
+        loop {
+            while let Ok((name, preview)) = self.receiver.try_recv() {
+                let preview = match preview {
+                    Ok(image) => Preview::Ready(image),
+                    Err(e) => Preview::Failed(e),
+                };
+                self.previews.insert(name, preview);
+            }
+            self.load_preview();
+
+            if let Err(e) = terminal.draw(|frame| self.draw(frame)) {
+                return Err(format!("Failed to draw: {}", e));
+            }
+
+            // wake up now and then to pick up previews that finished loading
+            match event::poll(Duration::from_millis(100)) {
+                Ok(true) => {},
+                Ok(false) => continue,
+                Err(e) => return Err(format!("Failed to read input: {}", e)),
+            }
+            let key = match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+                Ok(_) => continue,
+                Err(e) => return Err(format!("Failed to read input: {}", e)),
+            };
+            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                return Ok(None);
+            }
+
+            if self.confirming.is_some() {
+                if let Some(action) = self.confirm_key(key) {
+                    return Ok(Some(action));
+                }
+                continue;
+            }
+            match self.searching {
+                true => self.search_key(key),
+                false => match self.list_key(key) {
+                    Some(Ok(action)) => return Ok(Some(action)),
+                    Some(Err(())) => return Ok(None),
+                    None => {},
+                },
+            }
+        }
+    }
+
+    fn search_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char(c) => {
+                self.query.push(c);
+                self.filter();
+            },
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.filter();
+            },
+            KeyCode::Up => self.move_selection(-1),
+            KeyCode::Down => self.move_selection(1),
+            KeyCode::Enter | KeyCode::Esc | KeyCode::Tab => self.searching = false,
+            _ => {},
+        }
+    }
+
+    // only `y` goes ahead, any other key backs out
+    fn confirm_key(&mut self, key: KeyEvent) -> Option<(Action, Theme)> {
+        let (action, theme) = self.confirming.take()?;
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => Some((action, theme)),
+            _ => {
+                self.message = Some(format!("kept {}", theme.name));
+                None
+            },
+        }
+    }
+
+    // Some(Err) quits, Some(Ok) hands an action back to the caller
+    fn list_key(&mut self, key: KeyEvent) -> Option<Result<(Action, Theme), ()>> {
+        self.message = None;
+        let action = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Some(Err(())),
+            KeyCode::Char('/') | KeyCode::Tab => {
+                self.searching = true;
+                return None;
+            },
+            KeyCode::Char('j') | KeyCode::Down => {
+                self.move_selection(1);
+                return None;
+            },
+            KeyCode::Char('k') | KeyCode::Up => {
+                self.move_selection(-1);
+                return None;
+            },
+            KeyCode::Char('i') => Action::Install,
+            KeyCode::Char('u') => Action::Uninstall,
+            KeyCode::Char('e') => Action::Enable,
+            KeyCode::Char('d') => Action::Disable,
+            KeyCode::Char('U') => Action::Update,
+            _ => return None,
+        };
+
+        let theme = self.selected()?.clone();
+        let installed = theme._installed == Some(true);
+        let problem = match action {
+            Action::Install if installed => Some("is already installed"),
+            Action::Uninstall | Action::Enable | Action::Update if !installed => Some("isn't installed"),
+            Action::Enable if self.is_enabled(&theme) => Some("is already enabled"),
+            Action::Disable if !self.is_enabled(&theme) => Some("isn't enabled"),
+            _ => None,
+        };
+        match problem {
+            Some(problem) => {
+                self.message = Some(format!("{} {}", theme.name, problem));
+                None
+            },
+            None if action == Action::Uninstall => {
+                self.confirming = Some((action, theme));
+                None
+            },
+            None => Some(Ok((action, theme))),
+        }
+    }
+
+    fn move_selection(&mut self, by: isize) {
+        if self.filtered.is_empty() {
+            return;
+        }
+        let current = self.list.selected().unwrap_or(0) as isize;
+        let next = (current + by).clamp(0, self.filtered.len() as isize - 1);
+        self.list.select(Some(next as usize));
+    }
+
+    // fetching can take a while, so it happens in the background and the pane shows a placeholder meanwhile
+    fn load_preview(&mut self) {
+        let theme = match self.selected() {
+            Some(theme) if !theme.images.is_empty() => theme.clone(),
+            _ => return,
+        };
+        let key = theme.qualified_name();
+        if self.previews.contains_key(&key) {
+            return;
+        }
+        self.previews.insert(key.clone(), Preview::Loading);
+
+        let sender = self.sender.clone();
+        let theme_dir = self.theme_dir.clone();
+        tokio::spawn(async move {
+            let image = theme.fetch_preview(&theme_dir).await
+                .and_then(|bytes| preview::decode(&bytes))
+                .map(|image| image.thumbnail(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT));
+            // the browser may already be gone
+            let _ = sender.send((key, image));
+        });
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [search_area, main_area, help_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+        let [list_area, details_area] = Layout::horizontal([
+            Constraint::Percentage(40),
+            Constraint::Percentage(60),
+        ]).areas(main_area);
+
+        let search_style = match self.searching {
+            true => Style::default().fg(Color::Yellow),
+            false => Style::default(),
+        };
+        let search = Paragraph::new(self.query.as_str())
+            .block(Block::default().borders(Borders::ALL).border_style(search_style).title(" search "));
+        frame.render_widget(search, search_area);
+        if self.searching {
+            frame.set_cursor_position((search_area.x + 1 + self.query.chars().count() as u16, search_area.y + 1));
+        }
+
+        let items = self.filtered.iter().map(|theme| {
+            let marker = match (self.is_enabled(theme), theme._installed == Some(true)) {
+                (true, _) => Span::styled("● ", Style::default().fg(Color::Green)),
+                (false, true) => Span::styled("● ", Style::default()),
+                (false, false) => Span::raw("○ "),
+            };
+            ListItem::new(Line::from(vec![
+                marker,
+                Span::raw(theme.name.clone()),
+                Span::styled(format!(" {}", theme.get_author()), Style::default().add_modifier(Modifier::ITALIC | Modifier::DIM)),
+            ]))
+        }).collect::<Vec<ListItem>>();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(format!(" themes ({}) ", self.filtered.len())))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        self.draw_details(frame, details_area);
+
+        let help = match (&self.message, self.searching) {
+            _ if self.confirming.is_some() => {
+                let name = self.confirming.as_ref().map(|(_, theme)| theme.name.as_str()).unwrap_or_default();
+                Line::styled(format!("uninstall {} and delete its files? y/n", name), Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
+            },
+            (Some(message), _) => Line::styled(message.clone(), Style::default().fg(Color::Yellow)),
+            (None, true) => Line::raw("type to search  ↑↓ move  enter done"),
+            (None, false) => Line::raw("/ search  j/k move  i install  u uninstall  e enable  d disable  U update  q quit"),
+        };
+        frame.render_widget(Paragraph::new(help), help_area);
+    }
+
+    fn draw_details(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::default().borders(Borders::ALL).title(" details ");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let theme = match self.selected() {
+            Some(theme) => theme,
+            None => {
+                frame.render_widget(Paragraph::new("no themes match"), inner);
+                return;
+            },
+        };
+
+        let row = |label: &str, value: String| Line::from(vec![
+            Span::styled(format!("{:>10} ", label), Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(value),
+        ]);
+        let mut lines = vec![
+            Line::styled(theme.name.clone(), Style::default().add_modifier(Modifier::BOLD)),
+            Line::raw(theme.desc.clone()),
+            Line::raw(""),
+            row("author", theme.get_author()),
+            row("repo", theme.repo.clone()),
+            row("branch", if theme.branch.is_empty() { String::from("default") } else { theme.branch.clone() }),
+        ];
+        if let Some(registry) = &theme._registry {
+            lines.push(row("registry", registry.clone()));
+        }
+        if !theme.tags.is_empty() {
+            lines.push(row("tags", theme.tags.join(", ")));
+        }
+        lines.push(row("installed", if theme._installed == Some(true) { "yes" } else { "no" }.to_string()));
+        lines.push(row("enabled", if self.is_enabled(theme) { "yes" } else { "no" }.to_string()));
+
+        let text_height = (lines.len() as u16 + 1).min(inner.height);
+        let [text_area, preview_area] = Layout::vertical([
+            Constraint::Length(text_height),
+            Constraint::Min(0),
+        ]).areas(inner);
+        frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), text_area);
+
+        let preview = match self.previews.get(&theme.qualified_name()) {
+            Some(Preview::Ready(image)) => halfblock(image, preview_area),
+            Some(Preview::Loading) => vec![Line::raw("loading preview...")],
+            Some(Preview::Failed(e)) => vec![Line::styled(format!("no preview: {}", e), Style::default().fg(Color::Yellow))],
+            None => vec![Line::raw("no preview")],
+        };
+        frame.render_widget(Paragraph::new(preview), preview_area);
+    }
+}
+
+fn halfblock(image: &DynamicImage, area: Rect) -> Vec<Line<'static>> {
+    if area.width == 0 || area.height == 0 {
+        return Vec::new();
+    }
+    let image = preview::halfblock_image(image, area.width, area.height);
+    (0..image.height()).step_by(2).map(|y| {
+        Line::from((0..image.width()).map(|x| {
+            let top = image.get_pixel(x, y);
+            let mut style = Style::default().fg(Color::Rgb(top[0], top[1], top[2]));
+            if y + 1 < image.height() {
+                let bottom = image.get_pixel(x, y + 1);
+                style = style.bg(Color::Rgb(bottom[0], bottom[1], bottom[2]));
+            }
+            Span::styled("▀", style)
+        }).collect::<Vec<Span>>())
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn browser() -> Browser {
+        let mut theme = Theme::new("Nord", "https://github.com/someone/nord", "main");
+        theme._installed = Some(true);
+        let mut browser = Browser::new(vec![theme], Vec::new(), PathBuf::from("/nonexistent"));
+        browser.searching = false;
+        browser
+    }
+
+    fn press(browser: &mut Browser, c: char) -> Option<(Action, Theme)> {
+        let key = KeyEvent::from(KeyCode::Char(c));
+        match browser.confirming.is_some() {
+            true => browser.confirm_key(key),
+            false => browser.list_key(key).and_then(|action| action.ok()),
+        }
+    }
+
+    #[test]
+    fn uninstall_asks_first() {
+        let mut browser = browser();
+        assert!(press(&mut browser, 'u').is_none());
+        assert!(press(&mut browser, 'n').is_none());
+        assert_eq!(browser.message.as_deref(), Some("kept Nord"));
+
+        assert!(press(&mut browser, 'u').is_none());
+        let (action, theme) = press(&mut browser, 'y').unwrap();
+        assert_eq!(action, Action::Uninstall);
+        assert_eq!(theme.name, "Nord");
+
+        // other actions don't ask
+        assert_eq!(press(&mut browser, 'e').map(|(action, _)| action), Some(Action::Enable));
+    }
+}
@@ -1,75 +1,370 @@
 use std::path::PathBuf;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
-use crate::repo::find_theme;
-use crate::util::theme::Theme;
+use crate::util::output::Format;
+use crate::util::preview::Protocol;
+use crate::util::settings::{Cleanup, Color};
 
 #[derive(Parser)]
 #[command(version, name = "hyprtheme")]
-pub enum Hyprtheme {
-    Init,
+pub struct Hyprtheme {
+    /// print results as json for scripts, messages go to stderr
+    #[arg(long,value_enum,default_value = "text",global = true)]
+    pub output: Format,
+
+    /// defaults to $XDG_CONFIG_HOME/hyprtheme/config.toml
+    #[arg(long,global = true,env = "HYPRTHEME_SETTINGS")]
+    pub settings: Option<PathBuf>,
+
+    /// reload hyprland after changing the managed config
+    #[arg(long,global = true,env = "HYPRTHEME_AUTO_RELOAD")]
+    pub auto_reload: Option<bool>,
+
+    /// whose cleanup hooks run when themes are disabled or switched away from
+    #[arg(long,value_enum,global = true,env = "HYPRTHEME_CLEANUP")]
+    pub cleanup: Option<Cleanup>,
+
+    #[arg(long,value_enum,global = true,env = "HYPRTHEME_COLOR")]
+    pub color: Option<Color>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    Init(Init),
     Enable(Enable),
     Disable(Disable),
+    Switch(Switch),
+    Set(Set),
+    Component(Component),
+    Rollback(Rollback),
+    Status(Status),
+    Doctor(Doctor),
     List(List),
+    Search(Search),
+    Info(Info),
+    Browse(Browse),
     Install(Install),
     Uninstall(Uninstall),
     Update(Update),
+    Sync(Sync),
+    Registry(Registry),
     Uri(Uri),
 }
 
+#[derive(Parser)]
+pub struct Init {
+    /// defaults to $HYPRLAND_CONFIG, then $XDG_CONFIG_HOME/hypr/hyprland.conf
+    #[arg(long)]
+    pub hypr_config: Option<PathBuf>,
+
+    /// defaults to theme_dir from the settings file, the managed config goes in here
+    #[arg(short,long,env = "HYPRTHEME_THEME_DIR")]
+    pub theme_dir: Option<PathBuf>,
+
+    #[arg(short,long,env = "HYPRTHEME_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// put the source line at the end of hyprland.conf instead of the start
+    #[arg(long,default_value = "false",conflicts_with = "undo")]
+    pub append: bool,
+
+    /// take the source line back out, themes and the managed config are left alone
+    #[arg(long,default_value = "false")]
+    pub undo: bool,
+}
+
 #[derive(Parser)]
 pub struct Uri {
+    /// hyprtheme://<action>/<theme>[?theme_dir=<dir>&rev=<rev>]
     pub uri: String,
+
+    /// don't ask before doing what the link says
+    #[arg(short,long,default_value = "false")]
+    pub yes: bool,
 }
 
 #[derive(Parser)]
 pub struct Enable {
     pub theme: String,
 
-    #[arg(short,long,default_value="~/.config/hypr/themes/hyprtheme.conf")]
-    pub config: PathBuf,
+    #[arg(short,long,env = "HYPRTHEME_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// continue even if the theme's dependencies are missing
+    #[arg(short,long,default_value = "false")]
+    pub force: bool,
 }
 
 #[derive(Parser)]
 pub struct Disable {
     pub theme: String,
 
-    #[arg(short,long,default_value="~/.config/hypr/themes/hyprtheme.conf")]
-    pub config: PathBuf,
+    #[arg(short,long,env = "HYPRTHEME_CONFIG")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct Switch {
+    pub theme: String,
+
+    #[arg(short,long,env = "HYPRTHEME_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// continue even if the theme's dependencies are missing
+    #[arg(short,long,default_value = "false")]
+    pub force: bool,
+}
+
+#[derive(Parser)]
+pub struct Set {
+    pub theme: String,
+
+    pub variable: String,
+
+    /// leave out to reset the variable to the theme's default
+    pub value: Option<String>,
+
+    #[arg(short,long,env = "HYPRTHEME_CONFIG")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct Rollback {
+    /// how many versions to go back, 1 is the config before the last change
+    #[arg(default_value = "1")]
+    pub version: usize,
+
+    /// show the saved versions instead of rolling back
+    #[arg(short,long,default_value = "false")]
+    pub list: bool,
+
+    #[arg(short,long,env = "HYPRTHEME_CONFIG")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct Component {
+    #[command(subcommand)]
+    pub command: ComponentCommand,
+
+    #[arg(short,long,env = "HYPRTHEME_CONFIG",global=true)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+pub enum ComponentCommand {
+    Enable(ComponentToggle),
+    Disable(ComponentToggle),
+    List(ComponentList),
+}
+
+#[derive(Parser)]
+pub struct ComponentToggle {
+    pub theme: String,
+    pub component: String,
+}
+
+#[derive(Parser)]
+pub struct ComponentList {
+    pub theme: String,
+}
+
+#[derive(Parser)]
+pub struct Status {
+    #[arg(short,long,env = "HYPRTHEME_CONFIG")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct Doctor {
+    // not parse_path, a missing theme dir is one of the things to report
+    #[arg(short,long,env = "HYPRTHEME_THEME_DIR")]
+    pub theme_dir: Option<PathBuf>,
+
+    #[arg(short,long,env = "HYPRTHEME_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// repair what can be repaired without losing anything
+    #[arg(long,default_value = "false")]
+    pub fix: bool,
 }
 
 #[derive(Parser)]
 pub struct List {
 
+    /// only themes in the theme dir, without asking any registry
     #[arg(short,long,default_value = "false")]
     pub installed: bool,
 
-    #[arg(short,long,default_value="~/.config/hypr/themes",value_parser=parse_path)]
-    pub theme_dir: PathBuf,
+    /// only themes enabled in the managed config
+    #[arg(short,long,default_value = "false")]
+    pub enabled: bool,
+
+    /// only registry themes that aren't installed yet
+    #[arg(short,long,default_value = "false",conflicts_with_all=["installed","enabled","updates"])]
+    pub available: bool,
+
+    /// only installed themes with new commits upstream, with --offline as of their last fetch
+    #[arg(short,long,default_value = "false")]
+    pub updates: bool,
+
+    #[arg(short,long,env = "HYPRTHEME_THEME_DIR",value_parser=parse_path)]
+    pub theme_dir: Option<PathBuf>,
+
+    #[arg(short,long,env = "HYPRTHEME_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// only use the cached registry, never touch the network
+    #[arg(long,default_value = "false")]
+    pub offline: bool,
+}
+
+#[derive(Parser)]
+pub struct Search {
+    /// matched against name, description, author and tags
+    pub query: String,
+
+    #[arg(short,long,env = "HYPRTHEME_THEME_DIR",value_parser=parse_path)]
+    pub theme_dir: Option<PathBuf>,
+
+    #[arg(short,long,env = "HYPRTHEME_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// only use the cached registry, never touch the network
+    #[arg(long,default_value = "false")]
+    pub offline: bool,
+}
+
+#[derive(Parser)]
+pub struct Info {
+    /// registry theme or the name of an installed theme
+    pub theme: String,
+
+    #[arg(short,long,env = "HYPRTHEME_THEME_DIR",value_parser=parse_path)]
+    pub theme_dir: Option<PathBuf>,
+
+    #[arg(short,long,env = "HYPRTHEME_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// how to draw the preview image, auto picks from what the terminal supports
+    #[arg(long,value_enum,default_value = "auto")]
+    pub image: Protocol,
+
+    /// only use the cached registry, never touch the network
+    #[arg(long,default_value = "false")]
+    pub offline: bool,
+}
+
+#[derive(Parser)]
+pub struct Browse {
+    #[arg(short,long,env = "HYPRTHEME_THEME_DIR",value_parser=parse_path)]
+    pub theme_dir: Option<PathBuf>,
+
+    #[arg(short,long,env = "HYPRTHEME_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// only use the cached registry, never touch the network
+    #[arg(long,default_value = "false")]
+    pub offline: bool,
 }
 
 #[derive(Parser)]
 pub struct Install {
+    /// registry theme, git url or path, append @<tag|commit> to pin a revision
     pub theme: String,
 
-    #[arg(short,long,default_value="~/.config/hypr/themes",value_parser=parse_path)]
-    pub theme_dir: PathBuf,
+    #[arg(short,long,env = "HYPRTHEME_THEME_DIR",value_parser=parse_path)]
+    pub theme_dir: Option<PathBuf>,
+
+    /// only use the cached registry, never touch the network
+    #[arg(long,default_value = "false")]
+    pub offline: bool,
+
+    /// continue even if the theme's dependencies are missing
+    #[arg(short,long,default_value = "false")]
+    pub force: bool,
+
+    /// run the theme's install hook, which is skipped otherwise
+    #[arg(long,default_value = "false")]
+    pub run_hooks: bool,
 }
 
 #[derive(Parser)]
 pub struct Uninstall {
     pub theme: String,
 
-    #[arg(short,long,default_value="~/.config/hypr/themes",value_parser=parse_path)]
-    pub theme_dir: PathBuf,
+    #[arg(short,long,env = "HYPRTHEME_THEME_DIR",value_parser=parse_path)]
+    pub theme_dir: Option<PathBuf>,
+
+    /// only use the cached registry, never touch the network
+    #[arg(long,default_value = "false")]
+    pub offline: bool,
 }
 
 #[derive(Parser)]
 pub struct Update {
     pub theme: String,
 
-    #[arg(short,long,default_value="~/.config/hypr/themes",value_parser=parse_path)]
-    pub theme_dir: PathBuf,
+    #[arg(short,long,env = "HYPRTHEME_THEME_DIR",value_parser=parse_path)]
+    pub theme_dir: Option<PathBuf>,
+
+    /// only use the cached registry, never touch the network
+    #[arg(long,default_value = "false")]
+    pub offline: bool,
+
+    /// move to a branch, tag or commit instead of the latest commit of the current branch
+    #[arg(long)]
+    pub to: Option<String>,
+
+    /// only show the commits the update would bring in
+    #[arg(long,default_value = "false")]
+    pub dry_run: bool,
+}
+
+#[derive(Parser)]
+pub struct Sync {
+    #[arg(short,long,env = "HYPRTHEME_THEME_DIR",value_parser=parse_path)]
+    pub theme_dir: Option<PathBuf>,
+
+    /// remove installed themes that aren't in hyprtheme.lock, after asking
+    #[arg(long,default_value = "false")]
+    pub prune: bool,
+
+    /// only list what --prune would remove
+    #[arg(long,default_value = "false",requires = "prune")]
+    pub dry_run: bool,
+
+    /// prune without asking
+    #[arg(short,long,default_value = "false",requires = "prune")]
+    pub yes: bool,
+}
+
+#[derive(Parser)]
+pub struct Registry {
+    #[command(subcommand)]
+    pub command: RegistryCommand,
+}
+
+#[derive(Subcommand)]
+pub enum RegistryCommand {
+    Add(RegistryAdd),
+    Remove(RegistryRemove),
+    List,
+}
+
+#[derive(Parser)]
+pub struct RegistryAdd {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Parser)]
+pub struct RegistryRemove {
+    /// name or url of the registry
+    pub registry: String,
 }
 
 fn parse_path(path: &str) -> Result<PathBuf, String> {
@@ -4,201 +4,1035 @@ mod cli;
 mod util;
 
 use util::repo;
-use util::ansi::{red, reset,bold};
-use util::config::{Module,Config};
+use util::search;
+use util::git;
+use util::theme::Theme;
+use util::ansi::{self, green, red, reset, bold, italic, yellow};
+use util::config::{self, Module, Config};
+use util::history::History;
+use util::hyprland::Hyprland;
+use util::settings::{Cleanup, Registry, Settings};
+use util::lock::{Lockfile, SyncResult};
+use util::output::{self, ErrorCode, Format};
+use util::manifest::Manifest;
+use util::preview::{self, Protocol};
+use util::uri::{self, UriAction};
+use util::doctor::{self, Status};
 
-use cli::parse::Hyprtheme;
+use cli::browse::{Action, Browser};
+use cli::parse::{Browse, Command, ComponentCommand, Hyprtheme, Info, Init, List, RegistryCommand, Search, Sync, Uri};
 
-use expanduser::expanduser;
+use std::io::IsTerminal;
+use std::{path::{Path, PathBuf}, process::ExitCode};
 
-use std::fmt::format;
-use std::{path::PathBuf, process::ExitCode};
 
+fn load_settings(path: Option<PathBuf>) -> Result<Settings, ExitCode> {
+    match Settings::load(path) {
+        Ok(settings) => Ok(settings),
+        Err(e) => Err(output::fail(ErrorCode::Settings, e)),
+    }
+}
 
-async fn install_theme(theme: String, theme_dir: PathBuf) -> ExitCode {
-    let theme = match repo::find_theme(&theme,&theme_dir).await {
+async fn install_theme(theme: String, theme_dir: PathBuf, registries: &[Registry], offline: bool, force: bool, run_hooks: bool) -> ExitCode {
+    let (theme, rev) = repo::split_rev(&theme);
+    let theme = match repo::theme_from_source(theme) {
+        Ok(Some(theme)) => Ok(theme),
+        Ok(None) => repo::find_theme(theme,&theme_dir,registries,offline).await,
+        Err(e) => Err(e),
+    };
+    let theme = match theme {
         Ok(theme) => theme,
-        Err(e) => {
-            eprintln!("{}{}{}",reset() + &red(false) + &bold() ,e,reset());
-            return ExitCode::FAILURE;
+        Err(e) => return output::fail(ErrorCode::NotFound, e),
+    };
+    say!("found {}", theme);
+
+    match theme.install(theme_dir, rev, force, run_hooks) {
+        Ok(_) => {
+            say!();
+            output::done("install", Some(&theme.name), "installed");
+        },
+        Err(e) => return output::fail(ErrorCode::Install, e),
+    }
+    ExitCode::SUCCESS
+}
+
+async fn uninstall_theme(theme: String, theme_dir: PathBuf, registries: &[Registry], offline: bool) -> ExitCode {
+    let theme = match repo::find_theme(&theme,&theme_dir,registries,offline).await {
+        Ok(theme) => theme,
+        Err(e) => match repo::find_installed(&theme,&theme_dir) {
+            Some(theme) => theme,
+            None => return output::fail(ErrorCode::NotFound, e),
         },
     };
-    println!("found {}", theme);
+    say!("found {}", theme);
 
-    match theme.install(Some(theme_dir)) {
-        Ok(_) => println!("\ninstalled"),
-        Err(e) => {
-            eprintln!("{}{}{}",reset() + &red(false) + &bold() ,e,reset());
-            return ExitCode::FAILURE;
+    match theme.uninstall(theme_dir) {
+        Ok(_) => {
+            say!();
+            output::done("uninstall", Some(&theme.name), "uninstalled");
         },
+        Err(e) => return output::fail(ErrorCode::Uninstall, e),
     }
     ExitCode::SUCCESS
 }
 
-async fn uninstall_theme(theme: String, theme_dir: PathBuf) -> ExitCode {
-    let theme = match repo::find_theme(&theme,&theme_dir).await {
+async fn update_theme(theme: String, theme_dir: PathBuf, registries: &[Registry], offline: bool, to: Option<String>, dry_run: bool) -> ExitCode {
+    let theme = match repo::find_theme(&theme,&theme_dir,registries,offline).await {
         Ok(theme) => theme,
-        Err(e) => {
-            eprintln!("{}{}{}",reset() + &red(false) + &bold() ,e,reset());
-            return ExitCode::FAILURE;
+        Err(e) => match repo::find_installed(&theme,&theme_dir) {
+            Some(theme) => theme,
+            None => return output::fail(ErrorCode::NotFound, e),
         },
     };
-    println!("found {}", theme);
+    say!("found {}", theme);
+
+    let update = match theme.update(theme_dir, to.as_deref(), dry_run) {
+        Ok(update) => update,
+        Err(e) => return output::fail(ErrorCode::Update, e),
+    };
+
+    if output::is_json() {
+        output::print(&update);
+        return ExitCode::SUCCESS;
+    }
+    if update.added.is_empty() && update.dropped.is_empty() {
+        println!("Already up to date");
+        return ExitCode::SUCCESS;
+    }
+    if dry_run {
+        for commit in &update.added {
+            println!("{}+ {}{} {}", green(false), commit.hash, reset(), commit.summary);
+        }
+        for commit in &update.dropped {
+            println!("{}- {}{} {}", red(false), commit.hash, reset(), commit.summary);
+        }
+        return ExitCode::SUCCESS;
+    }
+    println!("Updated {}..{}", &update.from[..7], &update.to[..7]);
+    println!("\nupdated");
+    ExitCode::SUCCESS
+}
+
+fn expand(path: &std::path::Path) -> PathBuf {
+    PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).as_ref())
+}
+
+fn init(init_command: Init, settings: &Settings) -> ExitCode {
+    let hyprland_conf = match init_command.hypr_config {
+        Some(path) => expand(&path),
+        None => match config::hyprland_config_path() {
+            Ok(path) => path,
+            Err(e) => return output::fail(ErrorCode::Config, e),
+        },
+    };
+    let mut config = Config::new();
+    config.path = settings.config_path();
+
+    if init_command.undo {
+        return match config::remove_source_line(&hyprland_conf, &config.path) {
+            Ok(true) => {
+                output::done("init", None, format!("removed the source line from {}", hyprland_conf.display()));
+                ExitCode::SUCCESS
+            },
+            Ok(false) => {
+                output::done("init", None, format!("{} doesn't source {}", hyprland_conf.display(), config.path.display()));
+                ExitCode::SUCCESS
+            },
+            Err(e) => output::fail(ErrorCode::Config, e),
+        };
+    }
+
+    if let Err(e) = config.ensure_exists() {
+        return output::fail(ErrorCode::Config, e);
+    }
+    match config::add_source_line(&hyprland_conf, &config.path, init_command.append) {
+        Ok(true) => output::done("init", None, format!("added source={} to {}", config.path.display(), hyprland_conf.display())),
+        Ok(false) => output::done("init", None, "source line already exists"),
+        Err(e) => return output::fail(ErrorCode::Config, e),
+    }
+    ExitCode::SUCCESS
+}
+
+async fn enable_theme(theme: String, settings: &Settings, force: bool) -> ExitCode {
+    let mut config = match Config::from(settings).await {
+        Ok(config) => config,
+        Err(e) => return output::fail(ErrorCode::Config, e),
+    };
+    let module = match config.theme_path(&theme) {
+        Ok(path) => Module::new(None, path),
+        Err(e) => return output::fail(ErrorCode::NotFound, e),
+    };
+    if !module.path.exists() {
+        return output::fail(ErrorCode::NotFound, format!("Theme {} is not installed", module.name));
+    }
+    if let Err(e) = module.check_dependencies(force) {
+        return output::fail(ErrorCode::Dependencies, e);
+    }
+    let name = module.name.clone();
+    match config.add_module(module) {
+        Ok(_) => {},
+        Err(e) => return output::fail(ErrorCode::Config, e),
+    };
+    match config.apply() {
+        Ok(_) => output::done("enable", Some(&name), "enabled"),
+        Err(e) => return output::fail(ErrorCode::Apply, e),
+    }
+    if let Some(module) = config.modules.iter().find(|module| module.name == name) {
+        if let Err(e) = module.run_hook("enable") {
+            return output::fail(ErrorCode::Hook, e);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+// whose cleanup hooks to run once `outgoing` are disabled, `staying` were enabled before and still are
+fn cleanup_hooks<'a>(cleanup: Cleanup, outgoing: &'a [Module], staying: impl Iterator<Item = &'a Module>) -> Vec<&'a Module> {
+    match cleanup {
+        Cleanup::Outgoing => outgoing.iter().collect(),
+        Cleanup::All => outgoing.iter().chain(staying).collect(),
+        Cleanup::Never => Vec::new(),
+    }
+}
+
+async fn disable_theme(theme: String, settings: &Settings) -> ExitCode {
+    let mut config = match Config::from(settings).await {
+        Ok(config) => config,
+        Err(e) => return output::fail(ErrorCode::Config, e),
+    };
+    let path = match config.theme_path(&theme) {
+        Ok(path) => path,
+        Err(e) => return output::fail(ErrorCode::NotFound, e),
+    };
+    let removed = match config.remove_module(Module::new(None, path)) {
+        Ok(removed) => removed,
+        Err(e) => return output::fail(ErrorCode::NotFound, e),
+    };
+    match config.apply() {
+        Ok(_) => output::done("disable", Some(&removed.name), "disabled"),
+        Err(e) => return output::fail(ErrorCode::Apply, e),
+    }
+    for module in cleanup_hooks(settings.cleanup, std::slice::from_ref(&removed), config.modules.iter()) {
+        if let Err(e) = module.run_hook("cleanup") {
+            return output::fail(ErrorCode::Hook, e);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+async fn switch_theme(theme: String, settings: &Settings, force: bool) -> ExitCode {
+    let mut config = match Config::from(settings).await {
+        Ok(config) => config,
+        Err(e) => return output::fail(ErrorCode::Config, e),
+    };
+    let module = match config.theme_path(&theme) {
+        Ok(path) => Module::new(None, path),
+        Err(e) => return output::fail(ErrorCode::NotFound, e),
+    };
+    if let Err(e) = module.check_dependencies(force) {
+        return output::fail(ErrorCode::Dependencies, e);
+    }
+    let name = module.name.clone();
+    let already_enabled = config.modules.iter().any(|m| m.name == name);
+    let outgoing = match config.switch(module) {
+        Ok(outgoing) => outgoing,
+        Err(e) => return output::fail(ErrorCode::NotFound, e),
+    };
+    match config.apply() {
+        Ok(_) => output::done("switch", Some(&name), format!("switched to {}", name)),
+        Err(e) => return output::fail(ErrorCode::Apply, e),
+    }
+    for module in cleanup_hooks(settings.cleanup, &outgoing, config.modules.iter().filter(|_| already_enabled)) {
+        if let Err(e) = module.run_hook("cleanup") {
+            return output::fail(ErrorCode::Hook, e);
+        }
+    }
+    if !already_enabled {
+        if let Some(module) = config.modules.iter().find(|module| module.name == name) {
+            if let Err(e) = module.run_hook("enable") {
+                return output::fail(ErrorCode::Hook, e);
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+async fn run_doctor(fix: bool, settings: &Settings) -> ExitCode {
+    let theme_dir = settings.theme_dir();
+    let config_path = settings.config_path();
+    let hyprland_conf = match config::hyprland_config_path() {
+        Ok(hyprland_conf) => hyprland_conf,
+        Err(e) => return output::fail(ErrorCode::Config, e),
+    };
+
+    let mut checks = vec![
+        doctor::check_theme_dir(&theme_dir),
+        doctor::check_config(&config_path),
+        doctor::check_source_line(&hyprland_conf, &config_path),
+    ];
+    checks.extend(doctor::check_themes(&config_path, &theme_dir));
+    checks.push(doctor::check_git());
+    for registry in &settings.registries {
+        checks.push(doctor::check_registry(registry).await);
+    }
+    checks.push(doctor::check_hyprland());
+
+    if fix {
+        for check in checks.iter_mut() {
+            let repair = match &check.repair {
+                Some(repair) => repair.clone(),
+                None => continue,
+            };
+            match doctor::repair(&repair, settings).await {
+                Ok(_) => check.fixed = true,
+                Err(e) => check.message = format!("{}, fixing it failed: {}", check.message, e),
+            }
+        }
+    }
 
-    match theme.uninstall(Some(theme_dir)) {
-        Ok(_) => println!("\nuninstalled"),
+    let healthy = !checks.iter().any(|check| check.status == Status::Error && !check.fixed);
+    if output::is_json() {
+        output::print(&output::DoctorReport { checks, healthy });
+    } else {
+        for check in &checks {
+            let mark = match (check.status, check.fixed) {
+                (Status::Ok, _) | (_, true) => format!("{}✓", green(false)),
+                (Status::Warning, _) => format!("{}!", yellow(false)),
+                (Status::Error, _) => format!("{}✗", red(false)),
+            };
+            println!("{}{} {}{}: {}", mark, reset(), bold(), check.name, reset() + &check.message);
+            match (&check.fix, check.fixed) {
+                (_, true) => println!("    {}fixed{}", green(false), reset()),
+                (Some(fix), false) => println!("    {}fix: {}{}", italic(), fix, reset()),
+                (None, false) => {},
+            }
+        }
+        let fixable = checks.iter().filter(|check| check.repair.is_some() && !check.fixed).count();
+        if fixable > 0 {
+            println!("\n{} of these can be repaired with hyprtheme doctor --fix", fixable);
+        }
+    }
+
+    match healthy {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
+
+async fn list_themes(list: List, settings: &Settings) -> ExitCode {
+    let theme_dir = settings.theme_dir();
+    let registries = &settings.registries;
+    // enabled, installed and updates start from the theme dir, updates then fetch every theme's remote unless offline
+    let local = list.installed || list.enabled || list.updates;
+    let themes = match local {
+        true => repo::installed_themes(&theme_dir),
+        false => repo::fetch_themes(&theme_dir,registries,list.offline).await.map(|themes| themes.themes),
+    };
+    let themes = match themes {
+        Ok(themes) => themes,
+        Err(e) => return output::fail(ErrorCode::Registry, e),
+    };
+
+    let enabled = match config::enabled_themes(&settings.config_path()) {
+        Ok(enabled) => enabled,
         Err(e) => {
-            eprintln!("{}{}{}",reset() + &red(false) + &bold() ,e,reset());
-            return ExitCode::FAILURE;
+            output::warn(e);
+            Vec::new()
         },
+    };
+
+    let mut code = ExitCode::SUCCESS;
+    let mut listed = Vec::new();
+    for theme in themes {
+        let is_enabled = enabled.contains(&theme.name.to_lowercase().replace(" ", "_"));
+        if (list.enabled && !is_enabled) || (list.available && theme._installed == Some(true)) {
+            continue;
+        }
+        let mut updates = None;
+        let mut error = None;
+        if list.updates {
+            match theme.pending_updates(&theme_dir, list.offline) {
+                Ok(0) => continue,
+                Ok(pending) => updates = Some(pending),
+                Err(e) => {
+                    output::item_failed(&theme.name, &e);
+                    error = Some(e);
+                    code = ExitCode::FAILURE;
+                },
+            }
+        }
+
+        listed.push(output::ListedTheme { author: theme.get_author(), theme, enabled: is_enabled, updates, error });
     }
+
+    print_themes(listed, registries.len() > 1);
+    code
+}
+
+async fn search_themes(search: Search, settings: &Settings) -> ExitCode {
+    let registries = &settings.registries;
+    let themes = match repo::fetch_themes(&settings.theme_dir(),registries,search.offline).await {
+        Ok(themes) => themes.themes,
+        Err(e) => return output::fail(ErrorCode::Registry, e),
+    };
+    let enabled = config::enabled_themes(&settings.config_path()).unwrap_or_default();
+
+    let found = search::search(themes, &search.query);
+    if found.is_empty() {
+        return output::fail(ErrorCode::NotFound, format!("No themes match {}", search.query));
+    }
+    let listed = found.into_iter().map(|theme| {
+        let is_enabled = enabled.contains(&theme.name.to_lowercase().replace(" ", "_"));
+        output::ListedTheme { author: theme.get_author(), theme, enabled: is_enabled, updates: None, error: None }
+    }).collect();
+
+    print_themes(listed, registries.len() > 1);
     ExitCode::SUCCESS
 }
 
-async fn update_theme(theme: String, theme_dir: PathBuf) -> ExitCode {
-    let theme = match repo::find_theme(&theme,&theme_dir).await {
+fn print_themes(themes: Vec<output::ListedTheme>, show_registry: bool) {
+    if output::is_json() {
+        return output::print(&output::ThemeList { themes });
+    }
+    // failures were already reported
+    for listed in themes.iter().filter(|listed| listed.error.is_none()) {
+        print_theme(&listed.theme, show_registry, listed.enabled);
+    }
+}
+
+fn print_theme(theme: &Theme, show_registry: bool, enabled: bool) {
+    let mut line = format!("{} {}by {}{}", theme, italic(), theme.get_author(), reset());
+    if show_registry {
+        if let Some(registry) = &theme._registry {
+            line.push_str(&format!(" ({})", registry));
+        }
+    }
+    if enabled {
+        line.push_str(&format!(" {}{}enabled{}", green(false), bold(), reset()));
+    }
+    println!("{}", line);
+    if !theme.desc.is_empty() {
+        println!("    {}", theme.desc);
+    }
+}
+
+async fn theme_info(info: Info, settings: &Settings) -> ExitCode {
+    let theme_dir = settings.theme_dir();
+    let standardized = info.theme.to_lowercase().replace(" ", "_");
+    let theme = match repo::find_theme(&info.theme,&theme_dir,&settings.registries,info.offline).await {
         Ok(theme) => theme,
         Err(e) => {
-            eprintln!("{}{}{}",reset() + &red(false) + &bold() ,e,reset());
-            return ExitCode::FAILURE;
+            // themes installed from a url or path aren't in any registry
+            let installed = repo::installed_themes(&theme_dir).unwrap_or_default();
+            match installed.into_iter().find(|theme| theme.name == standardized) {
+                Some(theme) => theme,
+                None => return output::fail(ErrorCode::NotFound, e),
+            }
         },
     };
-    println!("found {}", theme);
 
-    match theme.update(Some(theme_dir)) {
-        Ok(_) => println!("\nupdated"),
-        Err(e) => {
-            eprintln!("{}{}{}",reset() + &red(false) + &bold() ,e,reset());
-            return ExitCode::FAILURE;
+    let name = theme.name.to_lowercase().replace(" ", "_");
+    let path = theme_dir.join(&name);
+    let installed = path.is_dir();
+    let manifest = Manifest::load(&path).ok().flatten();
+    let commit = git::open(&path).ok().and_then(|repository| git::head_commit(&repository).ok());
+    let pinned = Lockfile::load(&theme_dir).ok()
+        .and_then(|lockfile| lockfile.get(&name).and_then(|locked| locked.rev.clone()));
+    let enabled = config::enabled_themes(&settings.config_path())
+        .unwrap_or_default()
+        .contains(&name);
+
+    let info_result = output::ThemeInfo {
+        author: theme.get_author(),
+        version: manifest.and_then(|manifest| manifest.version),
+        installed,
+        enabled,
+        commit,
+        pinned,
+        theme,
+    };
+    if output::is_json() {
+        output::print(&info_result);
+        return ExitCode::SUCCESS;
+    }
+
+    let theme = &info_result.theme;
+    let row = |label: &str, value: &str| println!("{}{:>12}{} {}", bold(), label, reset(), value);
+    println!("{}{}{}", bold(), theme.name, reset());
+    if !theme.desc.is_empty() {
+        println!("{}", theme.desc);
+    }
+    println!();
+    row("author", &info_result.author);
+    if let Some(version) = &info_result.version {
+        row("version", version);
+    }
+    row("repo", if theme.repo.is_empty() { "-" } else { &theme.repo });
+    row("branch", if theme.branch.is_empty() { "default" } else { &theme.branch });
+    if let Some(registry) = &theme._registry {
+        row("registry", registry);
+    }
+    if !theme.tags.is_empty() {
+        row("tags", &theme.tags.join(", "));
+    }
+    row("installed", match installed {
+        true => format!("{}yes{} ({})", green(false), reset(), path.display()),
+        false => String::from("no"),
+    }.as_str());
+    row("enabled", match enabled {
+        true => format!("{}yes{}", green(false), reset()),
+        false => String::from("no"),
+    }.as_str());
+    if let Some(commit) = &info_result.commit {
+        row("commit", &commit[..7.min(commit.len())]);
+    }
+    if let Some(pinned) = &info_result.pinned {
+        row("pinned", pinned);
+    }
+
+    // nothing to draw on when stdout is piped somewhere, and no colors means no pictures unless asked for
+    let auto_off = !std::io::stdout().is_terminal() || !ansi::color();
+    if info.image == Protocol::None || theme.images.is_empty() || (info.image == Protocol::Auto && auto_off) {
+        return ExitCode::SUCCESS;
+    }
+    let (columns, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+    let preview = match theme.fetch_preview(&theme_dir).await {
+        Ok(bytes) => preview::render(&bytes, info.image, columns.min(80), (rows / 2).max(8)),
+        Err(e) => Err(e),
+    };
+    match preview {
+        Ok(preview) => {
+            println!();
+            print!("{}", preview);
         },
+        Err(e) => output::warn(format!("Couldn't show a preview: {}", e)),
     }
     ExitCode::SUCCESS
 }
 
+async fn browse_themes(browse: Browse, settings: &Settings) -> ExitCode {
+    if output::is_json() || !std::io::stdout().is_terminal() {
+        return output::fail(ErrorCode::Usage, "browse is interactive, use list or search from scripts");
+    }
+    let theme_dir = settings.theme_dir();
+    let registries = &settings.registries;
+    let themes = match repo::fetch_themes(&theme_dir,registries,browse.offline).await {
+        Ok(themes) => themes.themes,
+        Err(e) => return output::fail(ErrorCode::Registry, e),
+    };
+    let config_path = settings.config_path();
+    let enabled = config::enabled_themes(&config_path).unwrap_or_default();
 
-#[tokio::main]
-async fn main() -> ExitCode{
-    match Hyprtheme::parse() {
-        Hyprtheme::Init => {
-            let mut config = Config::new();
-            config.path = expanduser("~/.config/hypr/themes/hyprtheme.conf").unwrap();
-            config.ensure_exists().unwrap();
+    let mut browser = Browser::new(themes, enabled, theme_dir.clone());
+    loop {
+        let (action, theme) = match browser.run() {
+            Ok(Some(picked)) => picked,
+            Ok(None) => return ExitCode::SUCCESS,
+            Err(e) => return output::fail(ErrorCode::Usage, e),
+        };
+
+        // the browser gave the terminal back, so actions print (and prompt) like they would on the command line
+        let name = theme.name.to_lowercase().replace(" ", "_");
+        let code = match action {
+            Action::Install => install_theme(theme.qualified_name(), theme_dir.clone(), registries, browse.offline, false, false).await,
+            Action::Uninstall => uninstall_theme(theme.qualified_name(), theme_dir.clone(), registries, browse.offline).await,
+            Action::Enable => enable_theme(name.clone(), settings, false).await,
+            Action::Disable => disable_theme(name.clone(), settings).await,
+            Action::Update => update_theme(theme.qualified_name(), theme_dir.clone(), registries, browse.offline, None, false).await,
+        };
+
+        println!("\n{}press enter to go back to the browser{}", italic(), reset());
+        let mut line = String::new();
+        let _ = std::io::stdin().read_line(&mut line);
+
+        browser.refresh(config::enabled_themes(&config_path).unwrap_or_default());
+        browser.set_message(match code == ExitCode::SUCCESS {
+            true => format!("{} {}", theme.name, action.past_tense()),
+            false => format!("{} wasn't {}, see the output above", theme.name, action.past_tense()),
+        });
+    }
+}
 
-            let hyprland_conf = expanduser("~/.config/hypr/hyprland.conf").unwrap();
+// links come from any webpage, so nothing happens without the user agreeing to it in the terminal
+fn confirm(question: &str) -> bool {
+    if !std::io::stdin().is_terminal() {
+        return false;
+    }
+    eprint!("{}{} [y/N] {}", bold(), question, reset());
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+// hooks come from the theme's repository, so links name the script before it runs
+fn enable_hook_note(theme_path: &Path) -> Option<String> {
+    match Module::new(None, theme_path.to_path_buf()).hook_script("enable") {
+        Ok(Some(script)) => Some(format!(" This runs its enable hook {}.", script.display())),
+        _ => None,
+    }
+}
 
-            let content = match std::fs::read_to_string(&hyprland_conf) {
-                Ok(content) => content,
+async fn open_uri(uri: Uri, settings: &Settings) -> ExitCode {
+    let link = match uri::parse(&uri.uri) {
+        Ok(link) => link,
+        Err(e) => return output::fail(ErrorCode::Uri, e),
+    };
+    let theme_dir = match uri::resolve_theme_dir(link.theme_dir.as_deref(), &settings.theme_roots()) {
+        Ok(theme_dir) => theme_dir,
+        Err(e) => return output::fail(ErrorCode::Uri, format!("{} (allowed directories are theme_dir and theme_roots in {})", e, settings.path.display())),
+    };
+    let mut settings = settings.clone();
+    settings.override_paths(Some(theme_dir.clone()), None);
+    let settings = &settings;
+
+    let theme = match &link.rev {
+        Some(rev) => format!("{}@{}", link.theme, rev),
+        None => link.theme.clone(),
+    };
+    // enabled themes are referred to by their directory, without the registry in front
+    let name = link.theme.rsplit('/').next().unwrap_or(&link.theme).to_lowercase().replace(" ", "_");
+    let installed = theme_dir.join(&name).is_dir();
+    let hook_note = enable_hook_note(&theme_dir.join(&name)).unwrap_or_default();
+    let question = match link.action {
+        UriAction::Install => format!("Install {} into {}?", theme, theme_dir.display()),
+        UriAction::Uninstall => format!("Uninstall {} and delete it from {}?", link.theme, theme_dir.display()),
+        UriAction::Enable => format!("Enable {}?{}", name, hook_note),
+        UriAction::Switch => format!("Switch to {}, disabling every other theme?{}", name, hook_note),
+        UriAction::Update => format!("Update {} in {}?", link.theme, theme_dir.display()),
+        UriAction::Try if installed => format!("Enable {}?{}", name, hook_note),
+        UriAction::Try => format!("Install {} into {} and enable it?", theme, theme_dir.display()),
+        // only shows things
+        UriAction::Info => String::new(),
+    };
+    say!("{}{}{}", italic(), uri.uri, reset());
+    if link.action != UriAction::Info && !uri.yes && !confirm(&question) {
+        return output::fail(ErrorCode::Uri, format!("Not running {} without confirmation", link.action.name()));
+    }
+
+    match link.action {
+        UriAction::Install => install_theme(theme, theme_dir, &settings.registries, false, false, false).await,
+        UriAction::Uninstall => uninstall_theme(link.theme, theme_dir, &settings.registries, false).await,
+        UriAction::Enable => enable_theme(name, settings, false).await,
+        UriAction::Switch => switch_theme(name, settings, false).await,
+        UriAction::Update => update_theme(link.theme, theme_dir, &settings.registries, false, link.rev, false).await,
+        UriAction::Info => {
+            let info = Info { theme: link.theme, theme_dir: None, config: None, image: Protocol::Auto, offline: false };
+            theme_info(info, settings).await
+        },
+        UriAction::Try => {
+            // already installed is fine, the link is about trying it out
+            if !installed {
+                let code = install_theme(theme, theme_dir.clone(), &settings.registries, false, false, false).await;
+                if code != ExitCode::SUCCESS {
+                    return code;
+                }
+                // only now is there a theme.toml to tell which hook enabling it runs
+                if let Some(hook_note) = enable_hook_note(&theme_dir.join(&name)) {
+                    if !uri.yes && !confirm(&format!("Enable {}?{}", name, hook_note)) {
+                        return output::fail(ErrorCode::Uri, format!("Installed {} but not enabling it without confirmation", name));
+                    }
+                }
+            }
+            enable_theme(name, settings, false).await
+        },
+    }
+}
+
+fn sync_themes(sync: Sync, theme_dir: PathBuf) -> ExitCode {
+    let lockfile = match Lockfile::load(&theme_dir) {
+        Ok(lockfile) => lockfile,
+        Err(e) => return output::fail(ErrorCode::Sync, e),
+    };
+    // an empty lockfile would mean removing everything
+    if sync.prune && (!lockfile.exists() || lockfile.themes.is_empty()) {
+        return output::fail(ErrorCode::Sync, format!("Not pruning, {} is missing or empty", lockfile.path.display()));
+    }
+
+    let mut code = ExitCode::SUCCESS;
+    let mut report = output::SyncReport { themes: Vec::new() };
+    let synced = |name: &str, status, from: Option<&str>, to: Option<&str>, error: Option<String>| output::SyncedTheme {
+        name: name.to_string(),
+        status,
+        from: from.map(|from| from.to_string()),
+        to: to.map(|to| to.to_string()),
+        error,
+    };
+
+    for locked in &lockfile.themes {
+        match locked.sync(&theme_dir) {
+            Ok(SyncResult::UpToDate) => {
+                say!("{} up to date", locked.name);
+                report.themes.push(synced(&locked.name, "up_to_date", None, None, None));
+            },
+            Ok(SyncResult::Installed) => {
+                say!("{} installed", locked.name);
+                report.themes.push(synced(&locked.name, "installed", None, locked.commit.as_deref(), None));
+            },
+            Ok(SyncResult::Updated { from, to }) => {
+                say!("{} moved {}..{}", locked.name, &from[..7], &to[..7]);
+                report.themes.push(synced(&locked.name, "updated", Some(&from), Some(&to), None));
+            },
+            Err(e) => {
+                output::item_failed(&locked.name, &e);
+                report.themes.push(synced(&locked.name, "failed", None, None, Some(e)));
+                code = ExitCode::FAILURE;
+            },
+        }
+    }
+
+    if sync.prune {
+        let entries = match std::fs::read_dir(&theme_dir) {
+            Ok(entries) => entries,
+            Err(e) => return output::fail(ErrorCode::Sync, e),
+        };
+        let mut unlocked = entries.flatten()
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| !name.starts_with('.') && lockfile.get(name).is_none())
+            .collect::<Vec<String>>();
+        unlocked.sort();
+
+        if !unlocked.is_empty() {
+            say!("not in {}:", lockfile.path.display());
+            for name in &unlocked {
+                say!("  {}", name);
+            }
+        }
+        let question = format!("Remove {} themes from {}?", unlocked.len(), theme_dir.display());
+        if sync.dry_run {
+            for name in unlocked.drain(..) {
+                report.themes.push(synced(&name, "would_remove", None, None, None));
+            }
+        } else if !unlocked.is_empty() && !sync.yes && !confirm(&question) {
+            output::warn("Not pruning without confirmation, use --yes to skip the question");
+            for name in unlocked.drain(..) {
+                report.themes.push(synced(&name, "kept", None, None, None));
+            }
+            code = ExitCode::FAILURE;
+        }
+
+        for name in unlocked {
+            let path = theme_dir.join(&name);
+            match std::fs::remove_dir_all(&path) {
+                Ok(_) => {
+                    say!("{} removed", name);
+                    report.themes.push(synced(&name, "removed", None, None, None));
+                },
                 Err(e) => {
-                    eprintln!("{}{}{}",reset() + &red(false) + &bold() ,e,reset());
-                    return ExitCode::FAILURE;
+                    output::item_failed(&name, &e);
+                    report.themes.push(synced(&name, "failed", None, None, Some(e.to_string())));
+                    code = ExitCode::FAILURE;
                 },
-            };
+            }
+        }
+    }
 
-            let source_line = format!("source={}", config.path.display());
+    if output::is_json() {
+        output::print(&report);
+    }
+    code
+}
 
-            for line in content.lines() {
-                if line.trim() == source_line.trim() {
-                    println!("source line already exists");
-                    return ExitCode::SUCCESS;
-                }
+async fn status(settings: &Settings) -> ExitCode {
+    let config_path = settings.config_path();
+    let mut status = output::Status { config: config_path.clone(), enabled: Vec::new(), hyprland: None };
+
+    // don't create the managed config just to report that nothing is enabled
+    if config_path.exists() {
+        let config = match Config::from(settings).await {
+            Ok(config) => config,
+            Err(e) => return output::fail(ErrorCode::Config, e),
+        };
+        for module in &config.modules {
+            let commit = git::open(&module.path).ok().and_then(|repository| git::head_commit(&repository).ok());
+            let overrides = config.variables.get(&module.name);
+            status.enabled.push(output::EnabledTheme {
+                name: module.name.clone(),
+                path: module.path.clone(),
+                installed: module.path.exists(),
+                commit,
+                components: module.enabled_components(),
+                variables: module.variables(overrides).into_iter().collect(),
+            });
+        }
+    }
+
+    if let Some(hyprland) = Hyprland::from_env() {
+        let errors = hyprland.config_errors();
+        status.hyprland = Some(output::HyprlandStatus {
+            socket: hyprland.socket.clone(),
+            reachable: errors.is_ok(),
+            errors: errors.unwrap_or_default(),
+        });
+    }
+
+    if output::is_json() {
+        output::print(&status);
+        return ExitCode::SUCCESS;
+    }
+
+    println!("{}config{} {}", bold(), reset(), status.config.display());
+    if status.enabled.is_empty() {
+        println!("no themes enabled");
+    }
+    for theme in &status.enabled {
+        let state = match theme.installed {
+            true => green(false) + &bold() + "● ",
+            false => red(false) + &bold() + "✗ ",
+        };
+        let mut line = format!("{}{}{}{}", reset(), state, theme.name, reset());
+        if let Some(commit) = &theme.commit {
+            line.push_str(&format!(" {}{}{}", italic(), &commit[..7], reset()));
+        }
+        if !theme.components.is_empty() {
+            line.push_str(&format!(" [{}]", theme.components.join(",")));
+        }
+        println!("{}", line);
+        for (variable, value) in &theme.variables {
+            println!("    ${}={}", variable, value);
+        }
+    }
+    match &status.hyprland {
+        Some(hyprland) if !hyprland.reachable => println!("{}hyprland{} not reachable at {}", bold(), reset(), hyprland.socket.display()),
+        Some(hyprland) if hyprland.errors.is_empty() => println!("{}hyprland{} no config errors", bold(), reset()),
+        Some(hyprland) => {
+            println!("{}hyprland{} {}{} config errors{}", bold(), reset(), yellow(false), hyprland.errors.len(), reset());
+            for error in &hyprland.errors {
+                println!("    {}", error);
             }
+        },
+        None => println!("{}hyprland{} not running", bold(), reset()),
+    }
+    ExitCode::SUCCESS
+}
+
+// clap fails before we know which output was asked for, so look for it by hand
+fn wants_json() -> bool {
+    let args = std::env::args().collect::<Vec<String>>();
+    args.iter().any(|arg| arg == "--output=json")
+        || args.windows(2).any(|pair| pair[0] == "--output" && pair[1] == "json")
+}
 
-            println!("adding source line");
-            std::fs::write(&hyprland_conf, format!("{}\n\n{}",source_line,content)).unwrap();
+#[tokio::main]
+async fn main() -> ExitCode{
+    let hyprtheme = match Hyprtheme::try_parse() {
+        Ok(hyprtheme) => hyprtheme,
+        // --help and --version aren't errors
+        Err(e) if e.use_stderr() && wants_json() => {
+            output::set_format(Format::Json);
+            return output::fail(ErrorCode::Usage, e.to_string().trim());
+        },
+        Err(e) => e.exit(),
+    };
+    output::set_format(hyprtheme.output);
+
+    let mut settings = match load_settings(hyprtheme.settings) {
+        Ok(settings) => settings,
+        Err(code) => return code,
+    };
+    // what's in the file, for the commands that write it back
+    let stored = settings.clone();
+    // flags and $HYPRTHEME_* win over the settings file
+    if let Some(auto_reload) = hyprtheme.auto_reload {
+        settings.auto_reload = auto_reload;
+    }
+    if let Some(cleanup) = hyprtheme.cleanup {
+        settings.cleanup = cleanup;
+    }
+    if let Some(color) = hyprtheme.color {
+        settings.color = color;
+    }
+    ansi::set_color(settings.use_color(&std::io::stdout()), settings.use_color(&std::io::stderr()));
+
+    match hyprtheme.command {
+        Command::Init(init_command) => {
+            settings.override_paths(init_command.theme_dir.clone(), init_command.config.clone());
+            return init(init_command, &settings)
+        },
+        Command::Enable(enable) => {
+            settings.override_paths(None, enable.config);
+            return enable_theme(enable.theme, &settings, enable.force).await
         },
-        Hyprtheme::Enable(enable) => {
-            let mut config = Config::from(expanduser(enable.config.to_str().unwrap()).unwrap().to_owned()).await;
-            match config.add_module(Module::new(None, enable.config.parent().unwrap().join(enable.theme))) {
+        Command::Disable(disable) => {
+            settings.override_paths(None, disable.config);
+            return disable_theme(disable.theme, &settings).await
+        },
+        Command::Switch(switch) => {
+            settings.override_paths(None, switch.config);
+            return switch_theme(switch.theme, &settings, switch.force).await
+        },
+        Command::Set(set) => {
+            settings.override_paths(None, set.config);
+            let mut config = match Config::from(&settings).await {
+                Ok(config) => config,
+                Err(e) => return output::fail(ErrorCode::Config, e),
+            };
+            match config.set_variable(&set.theme, &set.variable, set.value) {
                 Ok(_) => {},
-                Err(e) => {
-                    eprintln!("{}{}{}",reset() + &red(false) + &bold() ,e,reset());
-                    return ExitCode::FAILURE;
-                },
+                Err(e) => return output::fail(ErrorCode::Config, e),
             };
             match config.apply() {
-                Ok(_) => println!("enabled"),
-                Err(e) => {
-                    eprintln!("{}{}{}",reset() + &red(false) + &bold() ,e,reset());
-                    return ExitCode::FAILURE;
-                },
+                Ok(_) => output::done("set", Some(&set.theme), "set"),
+                Err(e) => return output::fail(ErrorCode::Apply, e),
             }
         },
-        Hyprtheme::Disable(disable) => {
-            let mut config = Config::from(expanduser(disable.config.to_str().unwrap()).unwrap().to_owned()).await;
-            match config.cleanup() {
-                Ok(_) => println!("cleanup done"),
-                Err(e) => {
-                    eprintln!("{}{}{}",reset() + &red(false) + &bold() ,e,reset());
-                    return ExitCode::FAILURE;
-                },
-            }
-            match config.remove_module(Module::new(None, disable.config.parent().unwrap().join(disable.theme))) {
-                Ok(_) => {},
-                Err(e) => {
-                    eprintln!("{}{}{}",reset() + &red(false) + &bold() ,e,reset());
-                    return ExitCode::FAILURE;
+        Command::Component(component) => {
+            settings.override_paths(None, component.config);
+            let mut config = match Config::from(&settings).await {
+                Ok(config) => config,
+                Err(e) => return output::fail(ErrorCode::Config, e),
+            };
+            let (theme, toggled) = match component.command {
+                ComponentCommand::Enable(toggle) => (toggle.theme.clone(), config.set_component(&toggle.theme, &toggle.component, true)),
+                ComponentCommand::Disable(toggle) => (toggle.theme.clone(), config.set_component(&toggle.theme, &toggle.component, false)),
+                ComponentCommand::List(list) => {
+                    let module = match config.modules.iter().find(|module| module.name == list.theme) {
+                        Some(module) => module,
+                        None => return output::fail(ErrorCode::NotFound, format!("Theme {} is not enabled", list.theme)),
+                    };
+                    let enabled = module.enabled_components();
+                    let mut components = Vec::new();
+                    if let Some(manifest) = &module.manifest {
+                        for (name, declared) in &manifest.components {
+                            components.push(output::ComponentState {
+                                name: name.clone(),
+                                description: declared.description.clone(),
+                                enabled: enabled.contains(name),
+                            });
+                        }
+                    }
+                    if output::is_json() {
+                        output::print(&output::ComponentList { theme: list.theme, components });
+                        return ExitCode::SUCCESS;
+                    }
+                    for component in components {
+                        let state = if component.enabled { green(false) + &bold() + "● " } else { String::from("○ ") };
+                        println!("{}{}{}{} {}", reset(), state, component.name, reset(), component.description.as_deref().unwrap_or_default());
+                    }
+                    return ExitCode::SUCCESS;
                 },
             };
+            if let Err(e) = toggled {
+                return output::fail(ErrorCode::Config, e);
+            }
             match config.apply() {
-                Ok(_) => println!("disabled"),
-                Err(e) => {
-                    eprintln!("{}{}{}",reset() + &red(false) + &bold() ,e,reset());
-                    return ExitCode::FAILURE;
-                },
+                Ok(_) => output::done("component", Some(&theme), "applied"),
+                Err(e) => return output::fail(ErrorCode::Apply, e),
             }
         },
-        Hyprtheme::List(list) => {
-            for theme in repo::fetch_themes(&list.theme_dir,None).await.unwrap().themes {
-                println!("{}", theme);
+        Command::Rollback(rollback) => {
+            settings.override_paths(None, rollback.config);
+            let path = settings.config_path();
+            if rollback.list {
+                let history = History::new(&path);
+                let mut versions = Vec::new();
+                for version in history.versions() {
+                    // the enabled themes are the quickest way to tell versions apart
+                    let themes = config::enabled_themes(&history.path(version)).unwrap_or_default();
+                    versions.push(output::HistoryVersion { version, themes });
+                }
+                if output::is_json() {
+                    output::print(&output::HistoryList { versions });
+                    return ExitCode::SUCCESS;
+                }
+                for version in versions {
+                    println!("{} {}", version.version, version.themes.join(","));
+                }
+                return ExitCode::SUCCESS;
+            }
+            match Config::rollback(&settings, rollback.version).await {
+                Ok(_) => output::done("rollback", None, format!("rolled back to version {}", rollback.version)),
+                Err(e) => return output::fail(ErrorCode::History, e),
             }
         },
-        Hyprtheme::Install(install) => {
-            return install_theme(install.theme, install.theme_dir).await
+        Command::Status(status_command) => {
+            settings.override_paths(None, status_command.config);
+            return status(&settings).await
         },
-        Hyprtheme::Uninstall(uninstall) => {
-           return uninstall_theme(uninstall.theme, uninstall.theme_dir).await
+        Command::Doctor(doctor_command) => {
+            settings.override_paths(doctor_command.theme_dir, doctor_command.config);
+            return run_doctor(doctor_command.fix, &settings).await
         },
-        Hyprtheme::Update(update) => {
-            return update_theme(update.theme, update.theme_dir).await
+        Command::List(list) => {
+            settings.override_paths(list.theme_dir.clone(), list.config.clone());
+            return list_themes(list, &settings).await
         },
-        Hyprtheme::Uri(uri) => {
-            match uri.uri.strip_prefix("hyprtheme://") {
-                Some(uri) => {
-                    let uri = uri.split('+').collect::<Vec<&str>>();
-
-                    if uri.len() < 2 {
-                        eprintln!("{}{}{}",reset() + &red(false) + &bold() ,"Invalid uri",reset());
-                        return ExitCode::FAILURE;
+        Command::Search(search) => {
+            settings.override_paths(search.theme_dir.clone(), search.config.clone());
+            return search_themes(search, &settings).await
+        },
+        Command::Info(info) => {
+            settings.override_paths(info.theme_dir.clone(), info.config.clone());
+            return theme_info(info, &settings).await
+        },
+        Command::Browse(browse) => {
+            settings.override_paths(browse.theme_dir.clone(), browse.config.clone());
+            return browse_themes(browse, &settings).await
+        },
+        Command::Install(install) => {
+            settings.override_paths(install.theme_dir, None);
+            return install_theme(install.theme, settings.theme_dir(), &settings.registries, install.offline, install.force, install.run_hooks).await
+        },
+        Command::Uninstall(uninstall) => {
+            settings.override_paths(uninstall.theme_dir, None);
+            return uninstall_theme(uninstall.theme, settings.theme_dir(), &settings.registries, uninstall.offline).await
+        },
+        Command::Update(update) => {
+            settings.override_paths(update.theme_dir, None);
+            return update_theme(update.theme, settings.theme_dir(), &settings.registries, update.offline, update.to, update.dry_run).await
+        },
+        Command::Sync(sync) => {
+            settings.override_paths(sync.theme_dir.clone(), None);
+            return sync_themes(sync, settings.theme_dir())
+        },
+        Command::Registry(registry) => {
+            let mut settings = stored;
+            match registry.command {
+                RegistryCommand::Add(add) => {
+                    // store local registries as absolute paths so they work from any directory
+                    let url = match repo::local_path(&add.url) {
+                        Some(path) => match path.canonicalize() {
+                            Ok(path) if add.url.starts_with("file://") => format!("file://{}", path.display()),
+                            Ok(path) => path.display().to_string(),
+                            Err(e) => return output::fail(ErrorCode::Registry, format!("{}: {}", path.display(), e)),
+                        },
+                        None if add.url.contains("://") => add.url,
+                        None => return output::fail(ErrorCode::Registry, format!("{} is neither a url nor an existing path", add.url)),
+                    };
+                    match settings.add_registry(Registry::new(&add.name, &url)) {
+                        Ok(_) => {},
+                        Err(e) => return output::fail(ErrorCode::Registry, e),
                     }
-                    
-                    let command = uri[0];
-                    let theme = uri[1];
-                    
-                    let theme_dir = if uri.len() > 2 {
-                        PathBuf::from(uri[2])
-                    } else {
-                        expanduser("~/.config/hypr/themes").unwrap()
+                    match settings.save() {
+                        Ok(_) => output::done("registry add", None, format!("added registry {}", add.name)),
+                        Err(e) => return output::fail(ErrorCode::Settings, e),
+                    }
+                },
+                RegistryCommand::Remove(remove) => {
+                    let removed = match settings.remove_registry(&remove.registry) {
+                        Ok(removed) => removed,
+                        Err(e) => return output::fail(ErrorCode::Registry, e),
                     };
-
-                    match command.to_lowercase().as_str() {
-                        "install" => {
-                            install_theme(String::from(theme), theme_dir).await;
-                        },
-                        "uninstall" => {
-                            uninstall_theme(String::from(theme), theme_dir).await;
-                        },
-                        _ => {
-                            eprintln!("{}{}{}",reset() + &red(false) + &bold() ,"Invalid command",reset());
-                            return ExitCode::FAILURE;
-                        },
+                    match settings.save() {
+                        Ok(_) => output::done("registry remove", None, format!("removed registry {}", removed.name)),
+                        Err(e) => return output::fail(ErrorCode::Settings, e),
                     }
                 },
-                None => {
-                    eprintln!("{}{}{}",reset() + &red(false) + &bold() ,"Invalid uri",reset());
-                    return ExitCode::FAILURE;
+                RegistryCommand::List => {
+                    if output::is_json() {
+                        output::print(&output::RegistryList { registries: &settings.registries });
+                        return ExitCode::SUCCESS;
+                    }
+                    for registry in &settings.registries {
+                        println!("{}{}{} {}", bold(), registry.name, reset(), registry.url);
+                    }
                 },
             }
         },
+        Command::Uri(uri) => {
+            return open_uri(uri, &settings).await
+        },
     }
     return ExitCode::SUCCESS;
 }
@@ -1,105 +1,144 @@
+// the whole palette, not every colour is in use
+#![allow(dead_code)]
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static COLOR: AtomicBool = AtomicBool::new(true);
+static COLOR_ERR: AtomicBool = AtomicBool::new(true);
+
+// styles for `on_stderr`
+pub const ERROR: &str = "31;1";
+pub const WARNING: &str = "33";
+
+// stdout and stderr are decided on their own, either one can be piped while the other is a terminal,
+// off for stdout turns every function below into an empty string
+pub fn set_color(stdout: bool, stderr: bool) {
+    COLOR.store(stdout, Ordering::Relaxed);
+    COLOR_ERR.store(stderr, Ordering::Relaxed);
+}
+
+pub fn color() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+// a whole message styled for stderr, the functions below follow stdout
+pub fn on_stderr(style: &str, message: impl Display) -> String {
+    match COLOR_ERR.load(Ordering::Relaxed) {
+        true => format!("\x1b[0m\x1b[{}m{}\x1b[0m", style, message),
+        false => message.to_string(),
+    }
+}
+
+fn escape(code: &str) -> String {
+    match color() {
+        true => format!("\x1b[{}m", code),
+        false => String::new(),
+    }
+}
+
 pub fn red(bg: bool) -> String {
     if bg {
-        format!("\x1b[41m")
+        escape("41")
     } else {
-        format!("\x1b[31m")
+        escape("31")
     }
 }
 
 pub fn green(bg: bool) -> String {
     if bg {
-        format!("\x1b[42m")
+        escape("42")
     } else {
-        format!("\x1b[32m")
+        escape("32")
     }
 }
 
 pub fn yellow(bg: bool) -> String {
     if bg {
-        format!("\x1b[43m")
+        escape("43")
     } else {
-        format!("\x1b[33m")
+        escape("33")
     }
 }
 
 pub fn blue(bg: bool) -> String {
     if bg {
-        format!("\x1b[44m")
+        escape("44")
     } else {
-        format!("\x1b[34m")
+        escape("34")
     }
 }
 
 pub fn magenta(bg: bool) -> String {
     if bg {
-        format!("\x1b[45m")
+        escape("45")
     } else {
-        format!("\x1b[35m")
+        escape("35")
     }
 }
 
 pub fn cyan(bg: bool) -> String {
     if bg {
-        format!("\x1b[46m")
+        escape("46")
     } else {
-        format!("\x1b[36m")
+        escape("36")
     }
 }
 
 pub fn white(bg: bool) -> String {
     if bg {
-        format!("\x1b[47m")
+        escape("47")
     } else {
-        format!("\x1b[37m")
+        escape("37")
     }
 }
 
 pub fn black(bg: bool) -> String {
     if bg {
-        format!("\x1b[40m")
+        escape("40")
     } else {
-        format!("\x1b[30m")
+        escape("30")
     }
 }
 
 
 
 pub fn rgb(r: u8, g: u8, b: u8) -> String {
-    format!("\x1b[38;2;{};{};{}m", r, g, b)
+    escape(&format!("38;2;{};{};{}", r, g, b))
 }
 
 pub fn rgb_bg(r: u8, g: u8, b: u8) -> String {
-    format!("\x1b[48;2;{};{};{}m", r, g, b)
+    escape(&format!("48;2;{};{};{}", r, g, b))
 }
 
 pub fn reset() -> String {
-    format!("\x1b[0m")
+    escape("0")
 }
 
 pub fn bold() -> String {
-    format!("\x1b[1m")
+    escape("1")
 }
 
 pub fn italic() -> String {
-    format!("\x1b[3m")
+    escape("3")
 }
 
 pub fn underline() -> String {
-    format!("\x1b[4m")
+    escape("4")
 }
 
 pub fn blink() -> String {
-    format!("\x1b[5m")
+    escape("5")
 }
 
 pub fn reverse() -> String {
-    format!("\x1b[7m")
+    escape("7")
 }
 
 pub fn conceal() -> String {
-    format!("\x1b[8m")
+    escape("8")
 }
 
 pub fn strike() -> String {
-    format!("\x1b[9m")
+    escape("9")
 }
@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use expanduser::expanduser;
+use serde::{Deserialize, Serialize};
+
+// how long a cached registry index is used without asking the server again
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CachedIndex {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub fetched_at: u64,
+    pub body: String,
+}
+
+pub fn cache_dir() -> PathBuf {
+    let base = match std::env::var("XDG_CACHE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => expanduser("~/.cache").unwrap(),
+    };
+    base.join("hyprtheme").join("registry")
+}
+
+pub fn cache_path(url: &str) -> PathBuf {
+    // keep the file name readable and stable across versions instead of hashing
+    let name: String = url
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect();
+    cache_dir().join(format!("{}.json", name))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl CachedIndex {
+    pub fn new(url: &str, body: String, etag: Option<String>, last_modified: Option<String>) -> CachedIndex {
+        CachedIndex {
+            url: url.to_string(),
+            etag,
+            last_modified,
+            fetched_at: now(),
+            body,
+        }
+    }
+
+    pub fn load(url: &str) -> Option<CachedIndex> {
+        let content = std::fs::read_to_string(cache_path(url)).ok()?;
+        match serde_json::from_str::<CachedIndex>(&content) {
+            Ok(cached) if cached.url == url => Some(cached),
+            _ => None,
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = cache_path(&self.url);
+        if let Some(parent) = path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                return Err(format!("Failed to create {}: {}", parent.display(), e));
+            }
+        }
+        let content = match serde_json::to_string(self) {
+            Ok(content) => content,
+            Err(e) => return Err(e.to_string()),
+        };
+        match std::fs::write(&path, content) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Failed to write to {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(now().saturating_sub(self.fetched_at))
+    }
+
+    pub fn is_fresh(&self, ttl: Duration) -> bool {
+        self.age() < ttl
+    }
+
+    // server answered 304, the cached body is still current
+    pub fn touch(&mut self) -> Result<(), String> {
+        self.fetched_at = now();
+        self.save()
+    }
+}
@@ -1,13 +1,32 @@
-use std::path::{self, PathBuf};
+use std::collections::BTreeMap;
+use std::path::{self, Path, PathBuf};
 
-use expanduser::expanduser;
-
-use super::{repo, theme::{self, Theme}};
+use super::theme::{self, Theme};
+use crate::say;
+use super::manifest::{Manifest, DEFAULT_ENTRY};
+use super::history::{write_atomic, History};
+use super::hyprland::Hyprland;
+use super::output;
+use super::parser::{self, Block, Line};
+use super::settings::{config_home, Settings};
 
 pub struct Module {
     pub name: String,
     pub theme: Option<Theme>,
     pub path: PathBuf,
+    pub manifest: Option<Manifest>,
+    // components the user picked, None means the theme's defaults
+    pub components: Option<Vec<String>>,
+}
+
+fn load_manifest(path: &Path) -> Option<Manifest> {
+    match Manifest::load(path) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            output::warn(e);
+            None
+        },
+    }
 }
 
 impl Module {
@@ -15,23 +34,286 @@ impl Module {
         Module {
             name: path.file_name().unwrap().to_str().unwrap().to_string(),
             theme,
+            manifest: load_manifest(&path),
+            components: None,
             path,
         }
     }
-    
-    pub fn from_theme(theme: Theme) -> Module {
-        let path = expanduser("~/.config/hypr/themes/").unwrap().join(&theme.name.to_lowercase().replace(" ", "_"));
-        Module {
-            name: path.file_name().unwrap().to_str().unwrap().to_string(),
-            theme: Some(theme),
-            path,
+
+    // the config file sourced into hyprland, theme.toml wins over the registry entry
+    pub fn entry(&self) -> String {
+        if let Some(config) = self.manifest.as_ref().and_then(|manifest| manifest.config.clone()) {
+            return config;
+        }
+        match &self.theme {
+            Some(theme) if !theme.config.is_empty() => theme.config.clone(),
+            _ => String::from(DEFAULT_ENTRY),
+        }
+    }
+
+    // the variable holding the theme's path in hyprland
+    fn path_variable(&self) -> String {
+        match &self.theme {
+            Some(theme) => theme.name.to_lowercase().replace(" ", "_"),
+            None => self.name.clone(),
+        }
+    }
+
+    // variables declared in theme.toml, with the user's values taking precedence over the defaults
+    pub fn variables(&self, overrides: Option<&BTreeMap<String, String>>) -> Vec<(String, String)> {
+        let declared = match &self.manifest {
+            Some(manifest) => &manifest.variables,
+            None => return Vec::new(),
+        };
+        declared.iter().map(|(name, variable)| {
+            let value = overrides.and_then(|overrides| overrides.get(name)).unwrap_or(&variable.default);
+            (name.clone(), value.clone())
+        }).collect()
+    }
+
+    pub fn enabled_components(&self) -> Vec<String> {
+        let declared = match &self.manifest {
+            Some(manifest) => &manifest.components,
+            None => return Vec::new(),
+        };
+        match &self.components {
+            Some(components) => components.iter().filter(|name| declared.contains_key(*name)).cloned().collect(),
+            None => declared.iter().filter(|(_, component)| component.default).map(|(name, _)| name.clone()).collect(),
+        }
+    }
+
+    // a path from theme.toml or the registry, it has to stay inside the theme even after following symlinks
+    pub fn theme_file(&self, relative: &str) -> Result<PathBuf,String> {
+        let outside = || format!("{} of theme {} points outside of {}", relative, self.name, self.path.display());
+        let plain = Path::new(relative).components().all(|component| matches!(component, path::Component::Normal(_) | path::Component::CurDir));
+        if relative.is_empty() || !plain {
+            return Err(outside());
+        }
+
+        let file = self.path.join(relative);
+        if let (Ok(root), Ok(resolved)) = (self.path.canonicalize(), file.canonicalize()) {
+            if !resolved.starts_with(root) {
+                return Err(outside());
+            }
+        }
+        Ok(file)
+    }
+
+    // the entry config and the enabled components' configs, in the order they get sourced
+    pub fn sources(&self) -> Result<Vec<PathBuf>,String> {
+        let mut sources = vec![self.theme_file(&self.entry())?];
+        if let Some(manifest) = &self.manifest {
+            for name in self.enabled_components() {
+                if let Some(component) = manifest.components.get(&name) {
+                    sources.push(self.theme_file(&component.config)?);
+                }
+            }
         }
+        Ok(sources)
+    }
+
+    // the script `hook` would run, None when the theme doesn't have one
+    pub fn hook_script(&self, hook: &str) -> Result<Option<PathBuf>,String> {
+        let script = match self.manifest.as_ref().and_then(|manifest| manifest.hook(hook)) {
+            Some(script) => script.to_string(),
+            None if hook == "cleanup" => String::from("cleanup.sh"),
+            None => return Ok(None),
+        };
+        let script_path = self.theme_file(&script)?;
+        Ok(Some(script_path).filter(|script_path| script_path.exists()))
+    }
+
+    pub fn run_hook(&self, hook: &str) -> Result<(),String> {
+        let script_path = match self.hook_script(hook)? {
+            Some(script_path) => script_path,
+            None => return Ok(()),
+        };
+
+        match std::process::Command::new("chmod").arg("+x").arg(&script_path).output() {
+            Ok(_) => say!("chmod {} script of {}", hook, self.name),
+            Err(e) => return Err(format!("Failed to chmod {} script: {}", hook, e)),
+        }
+        let output = match std::process::Command::new(&script_path).current_dir(&self.path).output() {
+            Ok(output) => output,
+            Err(e) => return Err(format!("Failed to run {} script: {}", hook, e)),
+        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("{} script of {} failed ({}): {}", hook, self.name, output.status, stderr.trim()));
+        }
+        say!("{} of {} successful", hook, self.name);
+        Ok(())
+    }
+
+    // refuses to continue when something the theme's manifest asks for isn't there, unless forced
+    pub fn check_dependencies(&self, force: bool) -> Result<(),String> {
+        let manifest = match &self.manifest {
+            Some(manifest) => manifest,
+            None => return Ok(()),
+        };
+
+        let theme_dir = self.path.parent().unwrap_or(&self.path);
+        let missing = manifest.dependencies.missing(theme_dir);
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let list = missing.iter().map(|dependency| format!("  {}", dependency)).collect::<Vec<String>>().join("\n");
+        if force {
+            output::warn(format!("{} is missing dependencies, continuing anyway:\n{}", self.name, list));
+            Ok(())
+        } else {
+            Err(format!("{} is missing dependencies:\n{}\nuse --force to continue anyway", self.name, list))
+        }
+    }
+}
+
+// reload hyprland and fail if that brought up config errors it didn't have before
+fn reload(hyprland: &Hyprland, errors_before: &[String]) -> Result<(),String> {
+    // not being able to reach hyprland shouldn't stop the config from being written
+    if let Err(e) = hyprland.reload() {
+        output::warn(e);
+        return Ok(());
+    }
+
+    let errors = match hyprland.config_errors() {
+        Ok(errors) => errors,
+        Err(e) => {
+            output::warn(e);
+            return Ok(());
+        },
+    };
+    let introduced = errors.iter().filter(|error| !errors_before.contains(error)).map(|error| format!("  {}", error)).collect::<Vec<String>>();
+    if introduced.is_empty() {
+        return Ok(());
+    }
+    Err(format!("hyprland reported errors after the change:\n{}", introduced.join("\n")))
+}
+
+// where hyprland reads its config from, $HYPRLAND_CONFIG wins like it does for hyprland itself
+pub fn hyprland_config_path() -> Result<PathBuf,String> {
+    match std::env::var("HYPRLAND_CONFIG") {
+        Ok(path) if !path.is_empty() => Ok(PathBuf::from(shellexpand::tilde(&path).as_ref())),
+        _ => Ok(config_home().join("hypr").join("hyprland.conf")),
+    }
+}
+
+// `~`, `$HOME` and friends resolved, so differently written lines pointing at the same file compare equal
+fn normalize_source(path: &str) -> PathBuf {
+    let path = match shellexpand::full(path) {
+        Ok(path) => PathBuf::from(path.as_ref()),
+        Err(_) => PathBuf::from(path),
+    };
+    path.canonicalize().unwrap_or(path)
+}
+
+// line numbers of the `source = ...` lines in hyprland.conf that load the managed config
+fn find_source_lines(hyprland_conf: &str, config_path: &Path) -> Vec<usize> {
+    let config_path = config_path.canonicalize().unwrap_or(config_path.to_path_buf());
+    hyprland_conf.lines().enumerate().filter_map(|(index, line)| {
+        let line = line.split('#').next().unwrap_or_default();
+        let (key, value) = line.split_once('=')?;
+        if key.trim() != "source" || normalize_source(value.trim()) != config_path {
+            return None;
+        }
+        Some(index)
+    }).collect()
+}
+
+pub fn sources_config(hyprland_conf: &str, config_path: &Path) -> bool {
+    !find_source_lines(hyprland_conf, config_path).is_empty()
+}
+
+fn read_hyprland_config(hyprland_conf: &Path) -> Result<String,String> {
+    match std::fs::read_to_string(hyprland_conf) {
+        Ok(content) => Ok(content),
+        Err(e) => Err(format!("Failed to read {}: {}", hyprland_conf.display(), e)),
+    }
+}
+
+// not written atomically on purpose, hyprland.conf is often a symlink into someone's dotfiles
+fn write_hyprland_config(hyprland_conf: &Path, content: &str) -> Result<(),String> {
+    match std::fs::write(hyprland_conf, content) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write to {}: {}", hyprland_conf.display(), e)),
+    }
+}
+
+// adds the source line at the top of hyprland.conf, or the bottom with `append`, false when it was already there
+pub fn add_source_line(hyprland_conf: &Path, config_path: &Path, append: bool) -> Result<bool,String> {
+    let content = read_hyprland_config(hyprland_conf)?;
+    if sources_config(&content, config_path) {
+        return Ok(false);
+    }
+    let source_line = format!("source={}", config_path.display());
+    let content = match append {
+        true if content.is_empty() || content.ends_with('\n') => format!("{}\n{}\n", content, source_line),
+        true => format!("{}\n\n{}\n", content, source_line),
+        false => format!("{}\n\n{}", source_line, content),
+    };
+    write_hyprland_config(hyprland_conf, &content)?;
+    Ok(true)
+}
+
+// takes out every line sourcing the managed config along with the blank line init put next to it, false when there was none
+pub fn remove_source_line(hyprland_conf: &Path, config_path: &Path) -> Result<bool,String> {
+    let content = read_hyprland_config(hyprland_conf)?;
+    let found = find_source_lines(&content, config_path);
+    if found.is_empty() {
+        return Ok(false);
+    }
+
+    let lines = content.lines().collect::<Vec<&str>>();
+    let mut remove = found.clone();
+    for index in found {
+        let first = index == 0;
+        let last = index + 1 == lines.len();
+        if first && lines.get(1).is_some_and(|line| line.trim().is_empty()) {
+            remove.push(1);
+        } else if last && index > 0 && lines[index - 1].trim().is_empty() {
+            remove.push(index - 1);
+        }
+    }
+
+    let mut kept = lines.iter().enumerate()
+        .filter(|(index, _)| !remove.contains(index))
+        .map(|(_, line)| *line)
+        .collect::<Vec<&str>>()
+        .join("\n");
+    if content.ends_with('\n') && !kept.is_empty() {
+        kept.push('\n');
+    }
+    write_hyprland_config(hyprland_conf, &kept)?;
+    Ok(true)
+}
+
+// names of the themes enabled in the managed config at `path`, without creating it
+pub fn enabled_themes(path: &Path) -> Result<Vec<String>,String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    match parser::parse(&content) {
+        Ok(parsed) => Ok(parsed.modules.into_iter().map(|module| module.name).collect()),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
     }
 }
 
 pub struct Config {
     pub modules: Vec<Module>,
     pub path: PathBuf,
+    // where the enabled themes live, not necessarily next to the config
+    pub theme_dir: PathBuf,
+    // reload hyprland after writing
+    pub auto_reload: bool,
+    // user set theme variables, theme name -> variable -> value
+    pub variables: BTreeMap<String, BTreeMap<String, String>>,
+    // user lines and where the managed blocks sit between them
+    pub layout: Vec<Line>,
+    pub warnings: Vec<String>,
 }
 
 impl Config { 
@@ -39,15 +321,25 @@ impl Config {
         Config {
             modules: Vec::new(),
             path: PathBuf::new(),
+            theme_dir: PathBuf::new(),
+            auto_reload: true,
+            variables: BTreeMap::new(),
+            layout: Vec::new(),
+            warnings: Vec::new(),
         }
     }
 
     pub fn ensure_exists(&mut self) -> Result<(),String> {
         if !self.path.exists() {
 
-            let parent = self.path.parent().unwrap();
+            let parent = match self.path.parent() {
+                Some(parent) => parent,
+                None => return Err(format!("{} has no parent directory", self.path.display())),
+            };
             if !parent.exists() {
-                std::fs::create_dir_all(parent).unwrap();
+                if let Err(e) = std::fs::create_dir_all(parent) {
+                    return Err(format!("Failed to create {}: {}", parent.display(), e));
+                }
             }
 
             match std::fs::write(&self.path, "") {
@@ -59,40 +351,59 @@ impl Config {
         }
     }
 
-    pub async fn from(path: PathBuf) -> Config {
+    pub async fn from(settings: &Settings) -> Result<Config,String> {
         let mut config = Config::new();
-        config.path = path.to_owned();
+        config.path = settings.config_path();
+        config.theme_dir = settings.theme_dir();
+        config.auto_reload = settings.auto_reload;
 
-        let parent_path = path.parent().unwrap();
-
-        config.ensure_exists().unwrap();
+        config.ensure_exists()?;
 
         // read file at path
-        let file = std::fs::read_to_string(&config.path).unwrap();
+        let file = match std::fs::read_to_string(&config.path) {
+            Ok(file) => file,
+            Err(e) => return Err(format!("Failed to read {}: {}", config.path.display(), e)),
+        };
 
         // parse file
-        let mut lines = file.lines();
-        
-        for line in &mut lines {
-            if line.starts_with("# modules:"){
-                let modules = line.strip_prefix("# modules:").unwrap().split(",");
-                for module in modules {
-                    let module = module.trim();
-                    if module.len() > 0 {
-                        let module_path = parent_path.join(module);
-                        if module_path.exists() {
-                            let module = Module::new(None, module_path);
-                            config.add_module(module);
-                        }
-                    }
-                }
+        let parsed = match parser::parse(&file) {
+            Ok(parsed) => parsed,
+            Err(e) => return Err(format!("{}: {}", config.path.display(), e)),
+        };
+
+        for warning in parsed.warnings {
+            config.warnings.push(format!("{}: {}", config.path.display(), warning));
+        }
+
+        for parsed_module in parsed.modules {
+            let module_path = config.theme_dir.join(&parsed_module.name);
+            // keep it enabled so it comes back once the theme is there again, but don't source it
+            if !module_path.exists() {
+                config.warnings.push(format!(
+                    "{}: line {}: theme {} is enabled but {} doesn't exist",
+                    config.path.display(), parsed_module.line, parsed_module.name, module_path.display()
+                ));
             }
+            let mut module = Module::new(None, module_path);
+            module.components = parsed_module.components;
+            // the parser already dropped duplicates, and missing themes have to stay
+            config.modules.push(module);
         }
 
-        config
+        config.variables = parsed.variables;
+        config.layout = parsed.layout;
+
+        for warning in &config.warnings {
+            output::warn(warning);
+        }
+
+        Ok(config)
     }
 
     pub fn add_module(&mut self, module: Module) -> Result<(),String>  {
+        if !module.path.exists() {
+            return Err(format!("Theme {} is not installed", module.name));
+        }
         for m in &self.modules {
             if m.name == module.name {
                 return Err(format!("Module {} already exists", module.name));
@@ -102,32 +413,89 @@ impl Config {
         Ok(())
     }
 
-    pub fn remove_module(&mut self, module: Module) -> Result<(),String>  {
-        self.modules.retain(|m| {
-            module.name != m.name
-        });
-        Ok(())
+    pub fn remove_module(&mut self, module: Module) -> Result<Module,String>  {
+        match self.modules.iter().position(|m| m.name == module.name) {
+            Some(index) => Ok(self.modules.remove(index)),
+            None => Err(format!("Theme {} is not enabled", module.name)),
+        }
     }
 
-    pub fn cleanup(&mut self) -> Result<(),String>  {
-        for module in &self.modules {
-            let cleanup_path = module.path.join("cleanup.sh");
-            println!("cleanup_path: {}",&cleanup_path.display());
-            if cleanup_path.exists() {
-                match std::process::Command::new("chmod").arg("+x").arg(&cleanup_path).output() {
-                    Ok(_) => println!("chmod cleanup script of {}", module.name),
-                    Err(e) => return Err(format!("Failed to chmod cleanup script: {}", e)),
+    // make `module` the only enabled theme, returns the themes that were switched away from
+    pub fn switch(&mut self, module: Module) -> Result<Vec<Module>,String> {
+        if !module.path.exists() {
+            return Err(format!("Theme {} is not installed", module.name));
+        }
+
+        let (kept, outgoing): (Vec<Module>, Vec<Module>) = std::mem::take(&mut self.modules)
+            .into_iter()
+            .partition(|m| m.name == module.name);
+        self.modules = kept;
+        if self.modules.is_empty() {
+            self.modules.push(module);
+        }
+        Ok(outgoing)
+    }
+
+    // the directory of a theme named on the command line
+    pub fn theme_path(&self, theme: &str) -> Result<PathBuf,String> {
+        theme::check_name(theme)?;
+        Ok(self.theme_dir.join(theme))
+    }
+
+    // `None` resets the variable to the theme's default
+    pub fn set_variable(&mut self, theme: &str, variable: &str, value: Option<String>) -> Result<(),String> {
+        let module_path = self.theme_path(theme)?;
+        if !module_path.exists() {
+            return Err(format!("Theme {} is not installed", theme));
+        }
+
+        let module = Module::new(None, module_path);
+        let declared = module.manifest.as_ref().is_some_and(|manifest| manifest.variables.contains_key(variable));
+        if !declared {
+            return Err(format!("Theme {} has no variable {}", theme, variable));
+        }
+
+        match value {
+            Some(value) => {
+                if value.contains('\n') {
+                    return Err(String::from("Variable values can't span multiple lines"));
                 }
-                match std::process::Command::new(cleanup_path).output() {
-                    Ok(_) => println!("cleanup of {} successful", module.name),
-                    Err(e) => return Err(format!("Failed to run cleanup script: {}", e)),
+                self.variables.entry(module.name).or_default().insert(variable.to_string(), value);
+            },
+            None => {
+                if let Some(variables) = self.variables.get_mut(&module.name) {
+                    variables.remove(variable);
+                    if variables.is_empty() {
+                        self.variables.remove(&module.name);
+                    }
                 }
-            }
+            },
         }
         Ok(())
     }
 
-    pub fn build(&mut self) -> String {
+    pub fn set_component(&mut self, theme: &str, component: &str, enabled: bool) -> Result<(),String> {
+        let module = match self.modules.iter_mut().find(|module| module.name == theme) {
+            Some(module) => module,
+            None => return Err(format!("Theme {} is not enabled", theme)),
+        };
+
+        let declared = module.manifest.as_ref().is_some_and(|manifest| manifest.components.contains_key(component));
+        if !declared {
+            return Err(format!("Theme {} has no component {}", theme, component));
+        }
+
+        let mut components = module.enabled_components();
+        components.retain(|name| name != component);
+        if enabled {
+            components.push(component.to_string());
+        }
+        components.sort();
+        module.components = Some(components);
+        Ok(())
+    }
+
+    fn build_header(&self) -> String {
         // modules comment
         let mut config = String::from("# modules:");
         for module in &self.modules {
@@ -135,29 +503,120 @@ impl Config {
                 Some(theme) => theme.name.to_lowercase().replace(" ", "_"),
                 None => module.path.file_name().unwrap().to_str().unwrap().to_string(),
             };
-            config.push_str(format!("{},", theme_name).as_str());
+            match &module.components {
+                Some(components) => config.push_str(format!("{}[{}],", theme_name, components.join(",")).as_str()),
+                None => config.push_str(format!("{},", theme_name).as_str()),
+            }
         }config.push('\n');
 
+        for (theme, variables) in &self.variables {
+            for (variable, value) in variables {
+                config.push_str(format!("# set:{} {}={}\n", theme, variable, value).as_str());
+            }
+        }
+
+        config
+    }
+
+    fn build_variables(&self) -> String {
         // variables
-        config.push_str("\n# variables\n");
-        for module in &self.modules {
-            let theme_name = match &module.theme {
-                Some(theme) => theme.name.to_lowercase().replace(" ", "_"),
-                None => module.path.file_name().unwrap().to_str().unwrap().to_string(),
-            };
-            config.push_str(format!("${}={}\n", theme_name.to_lowercase().replace(" ","_"),module.path.display()).as_str());
+        let mut config = String::from("# variables\n");
+        for module in self.modules.iter().filter(|module| module.path.exists()) {
+            config.push_str(format!("${}={}\n", module.path_variable(), module.path.display()).as_str());
+        }
+        for module in self.modules.iter().filter(|module| module.path.exists()) {
+            for (variable, value) in module.variables(self.variables.get(&module.name)) {
+                config.push_str(format!("${}={}\n", variable, value).as_str());
+            }
         }config.push_str("\n# variables end\n");
 
+        config
+    }
+
+    // hyprland has one namespace for variables, so enabled themes can't share a name
+    fn check_variables(&self) -> Result<(),String> {
+        let mut owners: BTreeMap<String, &str> = BTreeMap::new();
+        for module in self.modules.iter().filter(|module| module.path.exists()) {
+            let declared = module.variables(None).into_iter().map(|(name, _)| name);
+            for name in std::iter::once(module.path_variable()).chain(declared) {
+                match owners.get(&name) {
+                    Some(owner) if *owner != module.name => {
+                        return Err(format!("Themes {} and {} both define ${}, only enable one of them", owner, module.name, name));
+                    },
+                    _ => { owners.insert(name, &module.name); },
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // every file the import block sources, in order
+    fn sources(&self) -> Result<Vec<PathBuf>,String> {
+        let mut sources = Vec::new();
+        for module in self.modules.iter().filter(|module| module.path.exists()) {
+            sources.extend(module.sources()?);
+        }
+        Ok(sources)
+    }
+
+    fn build_imports(&self) -> String {
         // import
-        config.push_str("\n# import\n");
-        for module in &self.modules {
-            config.push_str(format!("source={}/theme.conf\n", module.path.display()).as_str());
+        let mut config = String::from("# import\n");
+        // apply refuses to write anything when a source is outside its theme
+        for source in self.sources().unwrap_or_default() {
+            config.push_str(format!("source={}\n", source.display()).as_str());
         }config.push_str("\n# import end\n");
 
         config
     }
 
+    pub fn build(&mut self) -> String {
+        let mut layout = self.layout.clone();
+        if layout.is_empty() {
+            layout = vec![
+                Line::Managed(Block::Header),
+                Line::User(String::new()),
+                Line::Managed(Block::Variables),
+                Line::User(String::new()),
+                Line::Managed(Block::Imports),
+            ];
+        }
+
+        // give every managed block a place, variables have to come before the imports using them
+        if !layout.contains(&Line::Managed(Block::Header)) {
+            layout.insert(0, Line::Managed(Block::Header));
+            layout.insert(1, Line::User(String::new()));
+        }
+        if !layout.contains(&Line::Managed(Block::Imports)) {
+            layout.push(Line::User(String::new()));
+            layout.push(Line::Managed(Block::Imports));
+        }
+        if !layout.contains(&Line::Managed(Block::Variables)) {
+            let imports = layout.iter().position(|line| *line == Line::Managed(Block::Imports)).unwrap_or(layout.len());
+            layout.insert(imports, Line::User(String::new()));
+            layout.insert(imports, Line::Managed(Block::Variables));
+        }
+
+        let mut config = String::new();
+        for line in &layout {
+            match line {
+                Line::User(line) => {
+                    config.push_str(line);
+                    config.push('\n');
+                },
+                Line::Managed(Block::Header) => config.push_str(&self.build_header()),
+                Line::Managed(Block::Variables) => config.push_str(&self.build_variables()),
+                Line::Managed(Block::Imports) => config.push_str(&self.build_imports()),
+            }
+        }
+
+        config
+    }
+
     pub fn apply(&mut self) -> Result<(),String> {
+        self.sources()?;
+        self.check_variables()?;
+
         // apply config
         let config = self.build();
 
@@ -175,11 +634,194 @@ impl Config {
         //     }
         // }
 
-        match std::fs::write(&self.path, config){
-            Ok(_) => {
-                Ok(())
-            },
-            Err(e) => Err(format!("Failed to write to {}: {}", self.path.display(), e)),
+        // errors that were already there aren't the new config's fault
+        let hyprland = Hyprland::from_env().filter(|_| self.auto_reload);
+        let errors_before = hyprland.as_ref().and_then(|hyprland| hyprland.config_errors().ok()).unwrap_or_default();
+
+        let previous = std::fs::read_to_string(&self.path).unwrap_or_default();
+        let changed = previous != config;
+
+        write_atomic(&self.path, &config)?;
+
+        let validated = self.validate().and_then(|_| match &hyprland {
+            Some(hyprland) => reload(hyprland, &errors_before),
+            None => Ok(()),
+        });
+        if let Err(e) = validated {
+            if changed {
+                write_atomic(&self.path, &previous)?;
+                if let Some(hyprland) = &hyprland {
+                    let _ = hyprland.reload();
+                }
+                return Err(format!("{}\nrestored the previous {}", e, self.path.display()));
+            }
+            return Err(e);
+        }
+
+        // a rejected config never rotates the history, and the empty file `ensure_exists` leaves behind isn't worth keeping
+        if changed && !previous.is_empty() {
+            History::new(&self.path).push(&previous)?;
+        }
+
+        Ok(())
+    }
+
+    // read back what was written and make sure everything it sources is there
+    pub fn validate(&self) -> Result<(),String> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) => return Err(format!("Failed to read {}: {}", self.path.display(), e)),
+        };
+        if let Err(e) = parser::parse(&content) {
+            return Err(format!("{}: {}", self.path.display(), e));
+        }
+        self.check_variables()?;
+
+        for source in self.sources()? {
+            if !source.is_file() {
+                return Err(format!("{} sources {}, which doesn't exist", self.path.display(), source.display()));
+            }
+        }
+
+        Ok(())
+    }
+
+    // swap in an earlier version from the history, the current file becomes version 1 so this can be undone
+    pub async fn rollback(settings: &Settings, version: usize) -> Result<(),String> {
+        let path = settings.config_path();
+        let history = History::new(&path);
+        let content = history.get(version)?;
+        if let Err(e) = parser::parse(&content) {
+            return Err(format!("version {} of {}: {}", version, path.display(), e));
+        }
+
+        let hyprland = Hyprland::from_env().filter(|_| settings.auto_reload);
+        let errors_before = hyprland.as_ref().and_then(|hyprland| hyprland.config_errors().ok()).unwrap_or_default();
+
+        let previous = std::fs::read_to_string(&path).ok();
+        write_atomic(&path, &content)?;
+
+        // the restored version has to hold up like anything apply writes
+        let validated = match Config::from(settings).await {
+            Ok(config) => config.validate(),
+            Err(e) => Err(e),
+        }.and_then(|_| match &hyprland {
+            Some(hyprland) => reload(hyprland, &errors_before),
+            None => Ok(()),
+        });
+        if let Err(e) = validated {
+            match &previous {
+                Some(previous) => write_atomic(&path, previous)?,
+                None => { let _ = std::fs::remove_file(&path); },
+            }
+            if let Some(hyprland) = &hyprland {
+                let _ = hyprland.reload();
+            }
+            return Err(format!("{}\nkept the current {}", e, path.display()));
+        }
+
+        // only a rollback that went through can be undone
+        match previous {
+            Some(previous) => history.push(&previous),
+            None => Ok(()),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn theme(files: &[(&str, &str)]) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("theme");
+        std::fs::create_dir_all(&path).unwrap();
+        for (name, content) in files {
+            std::fs::write(path.join(name), content).unwrap();
+        }
+        (dir, path)
+    }
+
+    #[test]
+    fn theme_files_stay_inside_the_theme() {
+        let (dir, path) = theme(&[("theme.conf", "")]);
+        std::fs::write(dir.path().join("outside.sh"), "").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("outside.sh"), path.join("linked.sh")).unwrap();
+        let module = Module::new(None, path.clone());
+
+        assert_eq!(module.theme_file("theme.conf").unwrap(), path.join("theme.conf"));
+        assert_eq!(module.theme_file("./sub/missing.conf").unwrap(), path.join("./sub/missing.conf"));
+        for relative in ["", "/etc/hosts", "../outside.sh", "sub/../../outside.sh", "linked.sh"] {
+            assert!(module.theme_file(relative).is_err(), "{}", relative);
+        }
+    }
+
+    #[test]
+    fn manifest_paths_outside_the_theme_are_rejected() {
+        let (_dir, path) = theme(&[("theme.toml", "config = \"../theme.conf\"\n[hooks]\nenable = \"/bin/true\"\n")]);
+        let module = Module::new(None, path);
+        assert!(module.sources().is_err());
+        assert!(module.hook_script("enable").is_err());
+        assert!(module.run_hook("enable").is_err());
+    }
+
+    #[test]
+    fn failing_hooks_are_errors() {
+        let (_dir, path) = theme(&[
+            ("theme.toml", "[hooks]\nenable = \"enable.sh\"\n"),
+            ("enable.sh", "#!/bin/sh\necho broken >&2\nexit 1\n"),
+        ]);
+        let error = Module::new(None, path).run_hook("enable").unwrap_err();
+        assert!(error.contains("broken"), "{}", error);
+    }
+
+    #[test]
+    fn theme_names_stay_inside_the_theme_dir() {
+        let mut config = Config::new();
+        config.theme_dir = PathBuf::from("/themes");
+        assert_eq!(config.theme_path("nord").unwrap(), PathBuf::from("/themes/nord"));
+        for theme in ["", ".", "..", "../nord", "a/b", "/etc", ".hidden"] {
+            assert!(config.theme_path(theme).is_err(), "{}", theme);
+            assert!(config.set_variable(theme, "accent", None).is_err(), "{}", theme);
+        }
+    }
+
+    #[test]
+    fn themes_cant_share_variables() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = "[variables.accent]\ndefault = \"red\"\n";
+        for name in ["one", "two", "accent"] {
+            std::fs::create_dir_all(dir.path().join(name)).unwrap();
+        }
+        std::fs::write(dir.path().join("one/theme.toml"), manifest).unwrap();
+        std::fs::write(dir.path().join("two/theme.toml"), manifest).unwrap();
+        let module = |name: &str| Module::new(None, dir.path().join(name));
+
+        let mut config = Config::new();
+        config.modules = vec![module("one")];
+        assert!(config.check_variables().is_ok());
+
+        config.modules = vec![module("one"), module("two")];
+        let error = config.check_variables().unwrap_err();
+        assert!(error.contains("one and two") && error.contains("$accent"), "{}", error);
+
+        // the variable holding a theme's path counts too
+        config.modules = vec![module("one"), module("accent")];
+        assert!(config.check_variables().is_err());
+    }
+
+    #[test]
+    fn only_installed_themes_are_enabled() {
+        let (dir, path) = theme(&[("theme.conf", "")]);
+        let mut config = Config::new();
+        config.theme_dir = dir.path().to_path_buf();
+
+        let error = config.add_module(Module::new(None, dir.path().join("nope"))).unwrap_err();
+        assert_eq!(error, "Theme nope is not installed");
+        assert!(config.modules.is_empty());
+
+        assert!(config.add_module(Module::new(None, path.clone())).is_ok());
+        assert!(config.add_module(Module::new(None, path)).is_err());
+        assert_eq!(config.modules.len(), 1);
+    }
+}
@@ -0,0 +1,288 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Serialize;
+
+use crate::util::cache::CachedIndex;
+use crate::util::config::{self, Config, Module};
+use crate::util::hyprland::Hyprland;
+use crate::util::manifest::binary_exists;
+use crate::util::parser;
+use crate::util::repo::local_path;
+use crate::util::settings::{Registry, Settings};
+
+const REGISTRY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// part of the json output, don't rename variants
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+// what `--fix` is allowed to do on its own, nothing here throws away anything the user made
+#[derive(Debug, Clone)]
+pub enum Repair {
+    CreateThemeDir(PathBuf),
+    CreateConfig(PathBuf),
+    AddSourceLine { hyprland_conf: PathBuf, config: PathBuf },
+    // themes that are enabled but gone from the theme dir
+    DisableMissing(Vec<String>),
+}
+
+#[derive(Serialize, Debug)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub message: String,
+    // what the user can do about it
+    pub fix: Option<String>,
+    pub fixed: bool,
+    #[serde(skip)]
+    pub repair: Option<Repair>,
+}
+
+impl Check {
+    fn ok(name: &str, message: impl Into<String>) -> Check {
+        Check { name: name.to_string(), status: Status::Ok, message: message.into(), fix: None, fixed: false, repair: None }
+    }
+
+    fn problem(name: &str, status: Status, message: impl Into<String>, fix: impl Into<String>) -> Check {
+        Check { name: name.to_string(), status, message: message.into(), fix: Some(fix.into()), fixed: false, repair: None }
+    }
+
+    fn repair(mut self, repair: Repair) -> Check {
+        self.repair = Some(repair);
+        self
+    }
+}
+
+pub fn check_theme_dir(theme_dir: &Path) -> Check {
+    if !theme_dir.is_dir() {
+        return Check::problem(
+            "theme dir",
+            Status::Error,
+            format!("{} doesn't exist", theme_dir.display()),
+            format!("mkdir -p {}", theme_dir.display()),
+        ).repair(Repair::CreateThemeDir(theme_dir.to_path_buf()));
+    }
+
+    // the only reliable way to know is to try
+    let probe = theme_dir.join(".hyprtheme-doctor");
+    match std::fs::write(&probe, "") {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&probe);
+            Check::ok("theme dir", format!("{} is writable", theme_dir.display()))
+        },
+        Err(e) => Check::problem(
+            "theme dir",
+            Status::Error,
+            format!("{} is not writable: {}", theme_dir.display(), e),
+            format!("make sure {} belongs to you", theme_dir.display()),
+        ),
+    }
+}
+
+pub fn check_config(config_path: &Path) -> Check {
+    if !config_path.exists() {
+        return Check::problem(
+            "managed config",
+            Status::Error,
+            format!("{} doesn't exist", config_path.display()),
+            "run hyprtheme init",
+        ).repair(Repair::CreateConfig(config_path.to_path_buf()));
+    }
+    let content = match std::fs::read_to_string(config_path) {
+        Ok(content) => content,
+        Err(e) => return Check::problem(
+            "managed config",
+            Status::Error,
+            format!("Failed to read {}: {}", config_path.display(), e),
+            format!("make sure {} is readable", config_path.display()),
+        ),
+    };
+    match parser::parse(&content) {
+        Ok(_) => Check::ok("managed config", format!("{} parses", config_path.display())),
+        Err(e) => Check::problem(
+            "managed config",
+            Status::Error,
+            format!("{}: {}", config_path.display(), e),
+            "fix the line by hand or go back with hyprtheme rollback",
+        ),
+    }
+}
+
+pub fn check_source_line(hyprland_conf: &Path, config_path: &Path) -> Check {
+    let content = match std::fs::read_to_string(hyprland_conf) {
+        Ok(content) => content,
+        Err(e) => return Check::problem(
+            "source line",
+            Status::Error,
+            format!("Failed to read {}: {}", hyprland_conf.display(), e),
+            "make sure hyprland is set up",
+        ),
+    };
+    match config::sources_config(&content, config_path) {
+        true => Check::ok("source line", format!("{} sources {}", hyprland_conf.display(), config_path.display())),
+        false => Check::problem(
+            "source line",
+            Status::Error,
+            format!("{} doesn't source {}, enabled themes won't be loaded", hyprland_conf.display(), config_path.display()),
+            "run hyprtheme init",
+        ).repair(Repair::AddSourceLine { hyprland_conf: hyprland_conf.to_path_buf(), config: config_path.to_path_buf() }),
+    }
+}
+
+// every enabled theme is there and has the config it's supposed to source
+pub fn check_themes(config_path: &Path, theme_dir: &Path) -> Vec<Check> {
+    let content = match std::fs::read_to_string(config_path) {
+        Ok(content) => content,
+        Err(_) => return Vec::new(),
+    };
+    let parsed = match parser::parse(&content) {
+        Ok(parsed) => parsed,
+        // already reported by check_config
+        Err(_) => return Vec::new(),
+    };
+
+    let mut checks = Vec::new();
+    let mut missing = Vec::new();
+    let mut first_missing = None;
+    for parsed_module in parsed.modules {
+        let name = format!("theme {}", parsed_module.name);
+        let path = theme_dir.join(&parsed_module.name);
+        if !path.is_dir() {
+            first_missing.get_or_insert(checks.len());
+            missing.push(parsed_module.name.clone());
+            checks.push(Check::problem(
+                &name,
+                Status::Error,
+                format!("enabled on line {} but {} doesn't exist", parsed_module.line, path.display()),
+                format!("hyprtheme install {} or hyprtheme disable {}", parsed_module.name, parsed_module.name),
+            ));
+            continue;
+        }
+
+        let mut module = Module::new(None, path);
+        module.components = parsed_module.components;
+        let sources = match module.sources() {
+            Ok(sources) => sources,
+            Err(e) => {
+                checks.push(Check::problem(&name, Status::Error, e, format!("hyprtheme disable {} and report it to the theme's author", module.name)));
+                continue;
+            },
+        };
+        let absent = sources.iter()
+            .filter(|source| !source.is_file())
+            .map(|source| source.strip_prefix(&module.path).unwrap_or(source).display().to_string())
+            .collect::<Vec<String>>();
+        if !absent.is_empty() {
+            checks.push(Check::problem(
+                &name,
+                Status::Error,
+                format!("{} is missing {}", module.path.display(), absent.join(", ")),
+                format!("reinstall it with hyprtheme uninstall {} && hyprtheme install {}", module.name, module.name),
+            ));
+            continue;
+        }
+
+        let dependencies = match &module.manifest {
+            Some(manifest) => manifest.dependencies.missing(theme_dir),
+            None => Vec::new(),
+        };
+        match dependencies.is_empty() {
+            true => checks.push(Check::ok(&name, "installed and complete")),
+            false => checks.push(Check::problem(
+                &name,
+                Status::Warning,
+                format!("missing dependencies: {}", dependencies.iter().map(|d| d.to_string()).collect::<Vec<String>>().join(", ")),
+                "install them with your package manager, or hyprtheme install for themes",
+            )),
+        }
+    }
+
+    // one repair for all of them, so the config is only rewritten once
+    if let Some(index) = first_missing {
+        checks[index].repair = Some(Repair::DisableMissing(missing));
+    }
+    checks
+}
+
+// hyprtheme itself uses libgit2, but theme hooks commonly shell out to git
+pub fn check_git() -> Check {
+    match binary_exists("git") {
+        true => Check::ok("git", "git is on $PATH"),
+        false => Check::problem("git", Status::Warning, "git is not on $PATH, theme hooks that use it will fail", "install git"),
+    }
+}
+
+pub async fn check_registry(registry: &Registry) -> Check {
+    let name = format!("registry {}", registry.name);
+    if let Some(path) = local_path(&registry.url) {
+        let path = if path.is_dir() { path.join("themes.json") } else { path };
+        return match path.is_file() {
+            true => Check::ok(&name, format!("{} exists", path.display())),
+            false => Check::problem(&name, Status::Error, format!("{} doesn't exist", path.display()), format!("hyprtheme registry remove {}", registry.name)),
+        };
+    }
+
+    let reachable = match Client::builder().timeout(REGISTRY_TIMEOUT).build() {
+        Ok(client) => client.get(&registry.url).send().await.and_then(|res| res.error_for_status()).map(|_| ()),
+        Err(e) => Err(e),
+    };
+    match (reachable, CachedIndex::load(&registry.url)) {
+        (Ok(_), _) => Check::ok(&name, format!("{} is reachable", registry.url)),
+        (Err(e), Some(cached)) => Check::problem(
+            &name,
+            Status::Warning,
+            format!("{} is unreachable ({}), using the copy cached {}s ago", registry.url, e, cached.age().as_secs()),
+            "check your connection, --offline keeps using the cache",
+        ),
+        (Err(e), None) => Check::problem(
+            &name,
+            Status::Error,
+            format!("{} is unreachable and not cached: {}", registry.url, e),
+            format!("check the url, or hyprtheme registry remove {}", registry.name),
+        ),
+    }
+}
+
+pub fn check_hyprland() -> Check {
+    let hyprland = match Hyprland::from_env() {
+        Some(hyprland) => hyprland,
+        None => return Check::ok("hyprland", "not running inside a hyprland session, skipped"),
+    };
+    match hyprland.config_errors() {
+        Ok(errors) if errors.is_empty() => Check::ok("hyprland", "no config errors"),
+        Ok(errors) => Check::problem(
+            "hyprland",
+            Status::Error,
+            format!("hyprland reports config errors:\n{}", errors.iter().map(|error| format!("  {}", error)).collect::<Vec<String>>().join("\n")),
+            "fix the lines above, or go back with hyprtheme rollback if a theme change caused them",
+        ),
+        Err(e) => Check::problem("hyprland", Status::Warning, e, "make sure hyprland is running"),
+    }
+}
+
+pub async fn repair(repair: &Repair, settings: &Settings) -> Result<(), String> {
+    match repair {
+        Repair::CreateThemeDir(theme_dir) => std::fs::create_dir_all(theme_dir)
+            .map_err(|e| format!("Failed to create {}: {}", theme_dir.display(), e)),
+        Repair::CreateConfig(config_path) => {
+            let mut config = Config::new();
+            config.path = config_path.clone();
+            config.ensure_exists()
+        },
+        Repair::AddSourceLine { hyprland_conf, config } => config::add_source_line(hyprland_conf, config, false).map(|_| ()),
+        Repair::DisableMissing(themes) => {
+            let mut config = Config::from(settings).await?;
+            for theme in themes {
+                config.remove_module(Module::new(None, config.theme_dir.join(theme)))?;
+            }
+            config.apply()
+        },
+    }
+}
@@ -0,0 +1,307 @@
+use std::cell::Cell;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use git2::build::{CheckoutBuilder, RepoBuilder};
+use git2::{AutotagOption, Cred, CredentialType, FetchOptions, Oid, RemoteCallbacks, Repository};
+use indicatif::{ProgressBar, ProgressStyle};
+
+// libgit2's GIT_FETCH_DEPTH_UNSHALLOW
+const UNSHALLOW: i32 = i32::MAX;
+
+#[derive(Debug)]
+pub enum GitError {
+    Clone { repo: String, source: git2::Error },
+    Open { path: PathBuf, source: git2::Error },
+    Fetch { remote: String, source: git2::Error },
+    Checkout { rev: String, source: git2::Error },
+    DetachedHead(PathBuf),
+    NotFastForward { branch: String },
+    UnknownRev { rev: String },
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitError::Clone { repo, source } => write!(f, "Failed to clone {}: {}", repo, source.message()),
+            GitError::Open { path, source } => write!(f, "{} is not a git repository: {}", path.display(), source.message()),
+            GitError::Fetch { remote, source } => write!(f, "Failed to fetch from {}: {}", remote, source.message()),
+            GitError::Checkout { rev, source } => write!(f, "Failed to check out {}: {}", rev, source.message()),
+            GitError::DetachedHead(path) => write!(f, "{} is not on a branch", path.display()),
+            GitError::NotFastForward { branch } => write!(f, "Local changes on {} conflict with upstream, update it manually", branch),
+            GitError::UnknownRev { rev } => write!(f, "No branch, tag or commit named {}", rev),
+        }
+    }
+}
+
+impl std::error::Error for GitError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PullResult {
+    UpToDate,
+    Updated { from: String, to: String },
+}
+
+fn progress_bar(message: &str) -> ProgressBar {
+    let progress_bar = ProgressBar::new(0);
+    progress_bar.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner} {msg} [{bar:30}] {pos}/{len} {bytes}").unwrap()
+            .tick_chars("🌑🌒🌓🌔🌕🌖🌗🌘|")
+            .progress_chars("=> ")
+    );
+    progress_bar.enable_steady_tick(Duration::from_millis(50));
+    progress_bar.set_message(message.to_string());
+    progress_bar
+}
+
+fn fetch_options(progress_bar: &ProgressBar) -> FetchOptions<'_> {
+    let mut callbacks = RemoteCallbacks::new();
+
+    callbacks.transfer_progress(|progress| {
+        progress_bar.set_length(progress.total_objects() as u64);
+        if progress.received_objects() < progress.total_objects() {
+            progress_bar.set_position(progress.received_objects() as u64);
+        } else {
+            progress_bar.set_message("Resolving deltas");
+            progress_bar.set_length(progress.total_deltas() as u64);
+            progress_bar.set_position(progress.indexed_deltas() as u64);
+        }
+        true
+    });
+
+    // libgit2 keeps asking as long as we hand out credentials, so only try each kind once
+    let tried = Cell::new(CredentialType::empty());
+    callbacks.credentials(move |url, username, allowed| {
+        let remaining = allowed - tried.get();
+        if remaining.contains(CredentialType::SSH_KEY) {
+            tried.set(tried.get() | CredentialType::SSH_KEY);
+            return Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if remaining.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            tried.set(tried.get() | CredentialType::USER_PASS_PLAINTEXT);
+            let config = git2::Config::open_default()?;
+            return Cred::credential_helper(&config, url, username);
+        }
+        if remaining.contains(CredentialType::DEFAULT) {
+            tried.set(tried.get() | CredentialType::DEFAULT);
+            return Cred::default();
+        }
+        Err(git2::Error::from_str("no usable credentials"))
+    });
+
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks);
+    // tags are how themes mark releases worth pinning
+    options.download_tags(AutotagOption::All);
+    options
+}
+
+// `shallow` only fetches the tip like `git clone --depth 1`, for themes that don't need to move to a pinned revision
+pub fn clone(repo: &str, branch: &str, dest: &Path, shallow: bool) -> Result<Repository, GitError> {
+    let progress_bar = progress_bar("Receiving objects");
+
+    let mut options = fetch_options(&progress_bar);
+    // libgit2's local transport can't do shallow fetches, and copying a local repository is cheap anyway
+    let local = repo.starts_with("file://") || Path::new(repo).exists();
+    if shallow && !local {
+        options.depth(1);
+    }
+    let mut builder = RepoBuilder::new();
+    builder.fetch_options(options);
+    if !branch.is_empty() {
+        builder.branch(branch);
+    }
+
+    match builder.clone(repo, dest) {
+        Ok(repository) => {
+            progress_bar.finish_with_message("Cloned");
+            Ok(repository)
+        },
+        Err(source) => {
+            progress_bar.abandon_with_message("Clone failed");
+            Err(GitError::Clone { repo: repo.to_string(), source })
+        },
+    }
+}
+
+pub fn open(path: &Path) -> Result<Repository, GitError> {
+    Repository::open(path).map_err(|source| GitError::Open { path: path.to_path_buf(), source })
+}
+
+pub fn head_commit(repository: &Repository) -> Result<String, GitError> {
+    match repository.head().and_then(|head| head.peel_to_commit()) {
+        Ok(commit) => Ok(commit.id().to_string()),
+        Err(source) => Err(GitError::Checkout { rev: String::from("HEAD"), source }),
+    }
+}
+
+pub fn head_branch(repository: &Repository) -> Option<String> {
+    let head = repository.head().ok()?;
+    if !head.is_branch() {
+        return None;
+    }
+    head.shorthand().map(|branch| branch.to_string())
+}
+
+pub fn remote_url(repository: &Repository) -> Option<String> {
+    let remote = repository.find_remote("origin").ok()?;
+    remote.url().map(|url| url.to_string())
+}
+
+// fetch refspecs from origin, nothing means the remote's configured refspecs
+// and the whole history of shallow clones, since any revision may be asked for next
+pub fn fetch(repository: &Repository, refspecs: &[&str]) -> Result<(), GitError> {
+    let mut remote = repository.find_remote("origin")
+        .map_err(|source| GitError::Fetch { remote: String::from("origin"), source })?;
+    let remote_url = remote.url().unwrap_or("origin").to_string();
+
+    let progress_bar = progress_bar("Receiving objects");
+    let mut options = fetch_options(&progress_bar);
+    if refspecs.is_empty() && repository.is_shallow() {
+        options.depth(UNSHALLOW);
+    }
+    match remote.fetch(refspecs, Some(&mut options), None) {
+        Ok(_) => {
+            progress_bar.finish_with_message("Fetched");
+            Ok(())
+        },
+        Err(source) => {
+            progress_bar.abandon_with_message("Fetch failed");
+            Err(GitError::Fetch { remote: remote_url, source })
+        },
+    }
+}
+
+pub fn is_dirty(repository: &Repository) -> bool {
+    let mut options = git2::StatusOptions::new();
+    options.include_untracked(false);
+    match repository.statuses(Some(&mut options)) {
+        Ok(statuses) => !statuses.is_empty(),
+        Err(_) => true,
+    }
+}
+
+pub fn has_commit(repository: &Repository, commit: &str) -> bool {
+    match git2::Oid::from_str(commit) {
+        Ok(oid) => repository.find_commit(oid).is_ok(),
+        Err(_) => false,
+    }
+}
+
+// where an update would move a theme, `branch` is None when it ends up pinned to a detached commit
+pub struct Incoming {
+    pub from: Oid,
+    pub to: Oid,
+    pub branch: Option<String>,
+}
+
+// resolve `rev` against the fetched remote: a branch name, a tag or a (short) commit hash
+fn resolve(repository: &Repository, rev: &str) -> Result<(Oid, Option<String>), GitError> {
+    let unknown = |_| GitError::UnknownRev { rev: rev.to_string() };
+
+    if let Ok(reference) = repository.find_reference(&format!("refs/remotes/origin/{}", rev)) {
+        let commit = reference.peel_to_commit().map_err(unknown)?;
+        return Ok((commit.id(), Some(rev.to_string())));
+    }
+
+    let commit = repository.revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(unknown)?;
+    Ok((commit.id(), None))
+}
+
+// fetch from origin and work out what an update to `rev` (or the current branch's upstream) brings in,
+// without `fetch` only what was fetched before is looked at
+pub fn incoming(repository: &Repository, rev: Option<&str>, fetch: bool) -> Result<Incoming, GitError> {
+    let from = repository.head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(|source| GitError::Checkout { rev: String::from("HEAD"), source })?
+        .id();
+
+    if let Some(rev) = rev {
+        if fetch {
+            self::fetch(repository, &[])?;
+        }
+        let (to, branch) = resolve(repository, rev)?;
+        return Ok(Incoming { from, to, branch });
+    }
+
+    let branch = match head_branch(repository) {
+        Some(branch) => branch,
+        None => return Err(GitError::DetachedHead(repository.workdir().unwrap_or(repository.path()).to_path_buf())),
+    };
+
+    let tracking = format!("refs/remotes/origin/{}", branch);
+    if fetch {
+        self::fetch(repository, &[&format!("+refs/heads/{}:{}", branch, tracking)])?;
+    }
+
+    // FETCH_HEAD can't be used here, the tags fetched along with the branch end up in it too
+    let upstream = repository.find_reference(&tracking)
+        .and_then(|reference| repository.reference_to_annotated_commit(&reference))
+        .map_err(|source| GitError::Fetch { remote: String::from("origin"), source })?;
+
+    let (analysis, _) = repository.merge_analysis(&[&upstream])
+        .map_err(|source| GitError::Checkout { rev: branch.clone(), source })?;
+
+    if analysis.is_up_to_date() {
+        return Ok(Incoming { from, to: from, branch: Some(branch) });
+    }
+    if !analysis.is_fast_forward() {
+        return Err(GitError::NotFastForward { branch });
+    }
+
+    Ok(Incoming { from, to: upstream.id(), branch: Some(branch) })
+}
+
+// commits reachable from `to` but not from `from`, newest first, as (short hash, summary)
+pub fn log(repository: &Repository, from: Oid, to: Oid) -> Result<Vec<(String, String)>, GitError> {
+    let failed = |source| GitError::Checkout { rev: to.to_string(), source };
+
+    let mut revwalk = repository.revwalk().map_err(failed)?;
+    revwalk.push(to).map_err(failed)?;
+    revwalk.hide(from).map_err(failed)?;
+
+    let mut commits = Vec::new();
+    for oid in revwalk {
+        let commit = repository.find_commit(oid.map_err(failed)?).map_err(failed)?;
+        commits.push((commit.id().to_string()[..7].to_string(), commit.summary().unwrap_or("").to_string()));
+    }
+    Ok(commits)
+}
+
+// check out the target of `incoming`, moving the branch or detaching HEAD at a pinned commit
+pub fn apply(repository: &Repository, incoming: &Incoming) -> Result<PullResult, GitError> {
+    let to = incoming.to;
+    let checkout = |source| GitError::Checkout { rev: to.to_string(), source };
+
+    let head_matches = match (&incoming.branch, head_branch(repository)) {
+        (Some(branch), Some(current)) => *branch == current,
+        (None, None) => true,
+        _ => false,
+    };
+    if incoming.from == to && head_matches {
+        return Ok(PullResult::UpToDate);
+    }
+
+    let commit = repository.find_commit(to).map_err(checkout)?;
+    repository.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe())).map_err(checkout)?;
+
+    match &incoming.branch {
+        Some(branch) if head_branch(repository).as_deref() == Some(branch.as_str()) => {
+            let mut head = repository.head().map_err(checkout)?;
+            head.set_target(to, &format!("hyprtheme: move to {}", to)).map_err(checkout)?;
+        },
+        Some(branch) => {
+            let mut local = repository.branch(branch, &commit, true).map_err(checkout)?;
+            // a branch that only existed on the remote so far
+            let _ = local.set_upstream(Some(&format!("origin/{}", branch)));
+            repository.set_head(&format!("refs/heads/{}", branch)).map_err(checkout)?;
+        },
+        None => repository.set_head_detached(to).map_err(checkout)?,
+    }
+
+    Ok(PullResult::Updated { from: incoming.from.to_string(), to: to.to_string() })
+}
@@ -0,0 +1,142 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use expanduser::expanduser;
+
+// how many previous versions of hyprtheme.conf are kept around for `hyprtheme rollback`
+pub const HISTORY_SIZE: usize = 10;
+
+pub fn history_dir() -> PathBuf {
+    let base = match std::env::var("XDG_STATE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => expanduser("~/.local/state").unwrap(),
+    };
+    base.join("hyprtheme").join("history")
+}
+
+// write to a temp file next to `path` and rename it over, so hyprland never reads a half written config
+pub fn write_atomic(path: &Path, content: &str) -> Result<(), String> {
+    // renaming over a symlink would replace it, so write next to whatever it points at
+    let path = &std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy().to_string(),
+        None => return Err(format!("{} is not a file", path.display())),
+    };
+    let temp = path.with_file_name(format!(".{}.tmp", file_name));
+
+    let written = std::fs::File::create(&temp).and_then(|mut file| {
+        file.write_all(content.as_bytes())?;
+        file.sync_all()
+    });
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&temp);
+        return Err(format!("Failed to write to {}: {}", temp.display(), e));
+    }
+
+    match std::fs::rename(&temp, path) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp);
+            Err(format!("Failed to replace {}: {}", path.display(), e))
+        },
+    }
+}
+
+// previous versions of one config file, 1 is the most recent
+pub struct History {
+    pub dir: PathBuf,
+    pub size: usize,
+}
+
+impl History {
+    pub fn new(config_path: &Path) -> History {
+        // every managed config gets its own history, named after its path
+        let name: String = config_path
+            .display()
+            .to_string()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect();
+        History {
+            dir: history_dir().join(name),
+            size: HISTORY_SIZE,
+        }
+    }
+
+    pub fn path(&self, version: usize) -> PathBuf {
+        self.dir.join(format!("{}.conf", version))
+    }
+
+    pub fn versions(&self) -> Vec<usize> {
+        (1..=self.size).filter(|version| self.path(*version).exists()).collect()
+    }
+
+    pub fn get(&self, version: usize) -> Result<String, String> {
+        let path = self.path(version);
+        if version == 0 || !path.exists() {
+            return Err(format!("No version {} in history, there are {} saved", version, self.versions().len()));
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(content) => Ok(content),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    // push `content` as version 1, shifting the others back and dropping the oldest
+    pub fn push(&self, content: &str) -> Result<(), String> {
+        if let Err(e) = std::fs::create_dir_all(&self.dir) {
+            return Err(format!("Failed to create {}: {}", self.dir.display(), e));
+        }
+
+        let oldest = self.path(self.size);
+        if oldest.exists() {
+            if let Err(e) = std::fs::remove_file(&oldest) {
+                return Err(format!("Failed to remove {}: {}", oldest.display(), e));
+            }
+        }
+        for version in (1..self.size).rev() {
+            let path = self.path(version);
+            if path.exists() {
+                if let Err(e) = std::fs::rename(&path, self.path(version + 1)) {
+                    return Err(format!("Failed to rotate {}: {}", path.display(), e));
+                }
+            }
+        }
+
+        write_atomic(&self.path(1), content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symlinked_configs_stay_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("dotfiles.conf");
+        let link = dir.path().join("hyprtheme.conf");
+        std::fs::write(&target, "old").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomic(&link, "new").unwrap();
+        assert!(std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "new");
+
+        let plain = dir.path().join("plain.conf");
+        write_atomic(&plain, "fresh").unwrap();
+        assert_eq!(std::fs::read_to_string(&plain).unwrap(), "fresh");
+    }
+
+    #[test]
+    fn push_keeps_the_newest_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History { dir: dir.path().join("history"), size: 3 };
+        for content in ["1", "2", "3", "4"] {
+            history.push(content).unwrap();
+        }
+        assert_eq!(history.versions(), vec![1, 2, 3]);
+        let contents = history.versions().into_iter().map(|version| history.get(version).unwrap()).collect::<Vec<String>>();
+        assert_eq!(contents, vec!["4", "3", "2"]);
+    }
+}
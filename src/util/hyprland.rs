@@ -0,0 +1,127 @@
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// talks to hyprland's request socket, the same one hyprctl uses
+pub struct Hyprland {
+    pub socket: PathBuf,
+}
+
+impl Hyprland {
+    pub fn new(socket: PathBuf) -> Hyprland {
+        Hyprland { socket }
+    }
+
+    // None outside of a hyprland session
+    pub fn from_env() -> Option<Hyprland> {
+        let instance = match std::env::var("HYPRLAND_INSTANCE_SIGNATURE") {
+            Ok(instance) if !instance.is_empty() => instance,
+            _ => return None,
+        };
+
+        let runtime = match std::env::var("XDG_RUNTIME_DIR") {
+            Ok(dir) if !dir.is_empty() => PathBuf::from(dir).join("hypr").join(&instance).join(".socket.sock"),
+            _ => PathBuf::new(),
+        };
+        // hyprland before 0.40 kept its sockets in /tmp
+        let legacy = PathBuf::from("/tmp/hypr").join(&instance).join(".socket.sock");
+
+        if !runtime.exists() && legacy.exists() {
+            return Some(Hyprland::new(legacy));
+        }
+        Some(Hyprland::new(runtime))
+    }
+
+    pub fn request(&self, command: &str) -> Result<String, String> {
+        let failed = |e: std::io::Error| format!("Failed to talk to hyprland at {}: {}", self.socket.display(), e);
+
+        let mut stream = UnixStream::connect(&self.socket).map_err(failed)?;
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(failed)?;
+        stream.set_write_timeout(Some(TIMEOUT)).map_err(failed)?;
+
+        stream.write_all(command.as_bytes()).map_err(failed)?;
+
+        // hyprland closes the connection once it has answered
+        let mut response = String::new();
+        stream.read_to_string(&mut response).map_err(failed)?;
+        Ok(response)
+    }
+
+    pub fn reload(&self) -> Result<(), String> {
+        let response = self.request("reload")?;
+        match response.trim() {
+            "ok" => Ok(()),
+            response => Err(format!("hyprland refused to reload: {}", response)),
+        }
+    }
+
+    pub fn config_errors(&self) -> Result<Vec<String>, String> {
+        let response = self.request("j/configerrors")?;
+        if response.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        match serde_json::from_str::<Vec<String>>(&response) {
+            // no errors comes back as a single empty string
+            Ok(errors) => Ok(errors.into_iter().filter(|error| !error.is_empty()).collect()),
+            Err(e) => Err(format!("Unexpected answer to configerrors from hyprland: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::thread::JoinHandle;
+
+    // a socket that answers one request with `reply` and hands back what it was asked
+    fn serve(reply: &'static str) -> (tempfile::TempDir, Hyprland, JoinHandle<String>) {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join(".socket.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 256];
+            let read = stream.read(&mut request).unwrap();
+            stream.write_all(reply.as_bytes()).unwrap();
+            String::from_utf8_lossy(&request[..read]).to_string()
+        });
+        (dir, Hyprland::new(socket), server)
+    }
+
+    #[test]
+    fn reload_sends_reload() {
+        let (_dir, hyprland, server) = serve("ok");
+        assert!(hyprland.reload().is_ok());
+        assert_eq!(server.join().unwrap(), "reload");
+
+        let (_dir, hyprland, _server) = serve("nope");
+        assert_eq!(hyprland.reload().unwrap_err(), "hyprland refused to reload: nope");
+    }
+
+    #[test]
+    fn config_errors_are_parsed() {
+        let (_dir, hyprland, server) = serve(r#"["config error in line 3", "another one"]"#);
+        assert_eq!(hyprland.config_errors().unwrap(), vec!["config error in line 3", "another one"]);
+        assert_eq!(server.join().unwrap(), "j/configerrors");
+
+        for empty in [r#"[""]"#, "[]", ""] {
+            let (_dir, hyprland, _server) = serve(empty);
+            assert!(hyprland.config_errors().unwrap().is_empty(), "{:?}", empty);
+        }
+
+        let (_dir, hyprland, _server) = serve("unknown request");
+        let error = hyprland.config_errors().unwrap_err();
+        assert!(error.starts_with("Unexpected answer to configerrors"), "{}", error);
+    }
+
+    #[test]
+    fn missing_socket_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let hyprland = Hyprland::new(dir.path().join(".socket.sock"));
+        assert!(hyprland.reload().unwrap_err().starts_with("Failed to talk to hyprland"));
+    }
+}
@@ -0,0 +1,217 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::util::git::{self, Incoming};
+
+use git2::Oid;
+
+pub const LOCKFILE_NAME: &str = "hyprtheme.lock";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LockedTheme {
+    // name of the directory inside the theme dir
+    pub name: String,
+    pub registry: Option<String>,
+    pub repo: String,
+    pub branch: String,
+    // themes copied from a plain directory have no commit to pin
+    pub commit: Option<String>,
+    // tag or commit the theme was explicitly pinned to, updates won't move it
+    pub rev: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncResult {
+    UpToDate,
+    Installed,
+    Updated { from: String, to: String },
+}
+
+// lockfiles get shared between machines, so a name may only ever be a directory right inside the theme dir
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\'])
+        && !name.contains("..");
+    match valid {
+        true => Ok(()),
+        false => Err(format!("Invalid theme name {:?} in {}", name, LOCKFILE_NAME)),
+    }
+}
+
+impl LockedTheme {
+    // bring `<theme_dir>/<name>` to exactly the locked commit
+    pub fn sync(&self, theme_dir: &Path) -> Result<SyncResult, String> {
+        check_name(&self.name)?;
+        let dir = theme_dir.join(&self.name);
+
+        let commit = match &self.commit {
+            Some(commit) => commit,
+            None => {
+                if dir.exists() {
+                    return Ok(SyncResult::UpToDate);
+                }
+                let source = Path::new(&self.repo);
+                if !source.is_dir() {
+                    return Err(format!("{} was copied from {}, which doesn't exist here", self.name, self.repo));
+                }
+                let mut options = fs_extra::dir::CopyOptions::new();
+                options.content_only = true;
+                if let Err(e) = std::fs::create_dir_all(&dir) {
+                    return Err(e.to_string());
+                }
+                return match fs_extra::dir::copy(source, &dir, &options) {
+                    Ok(_) => Ok(SyncResult::Installed),
+                    Err(e) => Err(e.to_string()),
+                };
+            },
+        };
+
+        let oid = match Oid::from_str(commit) {
+            Ok(oid) => oid,
+            Err(e) => return Err(format!("Invalid commit {}: {}", commit, e.message())),
+        };
+        // pinned themes stay detached like after `install name@rev`, others follow their branch
+        let branch = match self.rev {
+            Some(_) => None,
+            None if self.branch.is_empty() => None,
+            None => Some(self.branch.clone()),
+        };
+
+        if !dir.exists() {
+            let cloned = git::clone(&self.repo, &self.branch, &dir, false).and_then(|repository| {
+                let from = oid_of(&git::head_commit(&repository)?);
+                git::apply(&repository, &Incoming { from, to: oid, branch })
+            });
+            return match cloned {
+                Ok(_) => Ok(SyncResult::Installed),
+                Err(e) => {
+                    if dir.exists() {
+                        let _ = std::fs::remove_dir_all(&dir);
+                    }
+                    Err(e.to_string())
+                },
+            };
+        }
+
+        let repository = git::open(&dir).map_err(|e| e.to_string())?;
+        let head = git::head_commit(&repository).map_err(|e| e.to_string())?;
+        if &head == commit {
+            return Ok(SyncResult::UpToDate);
+        }
+        if git::is_dirty(&repository) {
+            return Err(format!("{} has local changes, commit or discard them first", dir.display()));
+        }
+        if !git::has_commit(&repository, commit) {
+            git::fetch(&repository, &[]).map_err(|e| e.to_string())?;
+        }
+        git::apply(&repository, &Incoming { from: oid_of(&head), to: oid, branch }).map_err(|e| e.to_string())?;
+
+        Ok(SyncResult::Updated { from: head, to: commit.clone() })
+    }
+}
+
+fn oid_of(commit: &str) -> Oid {
+    Oid::from_str(commit).unwrap_or(Oid::zero())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Lockfile {
+    #[serde(default, rename = "theme")]
+    pub themes: Vec<LockedTheme>,
+
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl Lockfile {
+    pub fn load(theme_dir: &Path) -> Result<Lockfile, String> {
+        let path = theme_dir.join(LOCKFILE_NAME);
+        if !path.exists() {
+            return Ok(Lockfile { themes: Vec::new(), path });
+        }
+
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        match toml::from_str::<Lockfile>(&content) {
+            Ok(mut lockfile) => {
+                lockfile.path = path;
+                Ok(lockfile)
+            },
+            Err(e) => Err(format!("Failed to parse {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn save(&mut self) -> Result<(), String> {
+        // stable order keeps diffs small when the lockfile lives in dotfiles
+        self.themes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let content = match toml::to_string_pretty(self) {
+            Ok(content) => content,
+            Err(e) => return Err(e.to_string()),
+        };
+        let content = format!("# generated by hyprtheme, reproduce with `hyprtheme sync`\n\n{}", content);
+
+        match std::fs::write(&self.path, content) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Failed to write to {}: {}", self.path.display(), e)),
+        }
+    }
+
+    // load returns an empty lockfile when there is none
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LockedTheme> {
+        self.themes.iter().find(|theme| theme.name == name)
+    }
+
+    pub fn insert(&mut self, theme: LockedTheme) {
+        self.remove(&theme.name);
+        self.themes.push(theme);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<LockedTheme> {
+        let index = self.themes.iter().position(|theme| theme.name == name)?;
+        Some(self.themes.remove(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_stay_inside_the_theme_dir() {
+        assert!(check_name("theme").is_ok());
+        assert!(check_name("my-theme_2").is_ok());
+        for name in ["", "..", "../x", "a/../b", "/etc", "a/b", ".hidden", "a\\b"] {
+            assert!(check_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn sync_rejects_escaping_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme_dir = dir.path().join("themes");
+        std::fs::create_dir_all(&theme_dir).unwrap();
+        let source = dir.path().join("source");
+        std::fs::create_dir_all(&source).unwrap();
+
+        let locked = LockedTheme {
+            name: String::from("../escaped"),
+            registry: None,
+            repo: source.display().to_string(),
+            branch: String::new(),
+            commit: None,
+            rev: None,
+        };
+        assert!(locked.sync(&theme_dir).is_err());
+        assert!(!dir.path().join("escaped").exists());
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::util::repo::theme_installed;
+
+pub const MANIFEST_NAME: &str = "theme.toml";
+
+pub const DEFAULT_ENTRY: &str = "theme.conf";
+
+// everything in theme.toml is optional, a theme directory with only a theme.conf keeps working
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Manifest {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    pub description: Option<String>,
+    // keywords for `hyprtheme search`, e.g. "dark", "catppuccin" or "minimal"
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // screenshots shown by `hyprtheme info`, urls or paths relative to the theme
+    #[serde(default)]
+    pub images: Vec<String>,
+    // hyprland config sourced when the theme is enabled, relative to the theme
+    pub config: Option<String>,
+    #[serde(default)]
+    pub hooks: Hooks,
+    #[serde(default)]
+    pub variables: BTreeMap<String, Variable>,
+    #[serde(default)]
+    pub components: BTreeMap<String, Component>,
+    #[serde(default)]
+    pub dependencies: Dependencies,
+}
+
+// scripts relative to the theme, run from inside the theme directory
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Hooks {
+    pub install: Option<String>,
+    pub enable: Option<String>,
+    // run when the theme is disabled, defaults to cleanup.sh
+    pub cleanup: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Variable {
+    pub default: String,
+    pub description: Option<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Component {
+    pub config: String,
+    pub description: Option<String>,
+    // whether the component is on when the user hasn't picked any
+    #[serde(default = "default_enabled")]
+    pub default: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Dependencies {
+    // programs that have to be on $PATH, e.g. eww, ags, waybar or swww
+    #[serde(default)]
+    pub binaries: Vec<String>,
+    // font families as reported by fc-list
+    #[serde(default)]
+    pub fonts: Vec<String>,
+    // other themes that have to be installed next to this one
+    #[serde(default)]
+    pub themes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dependency {
+    Binary(String),
+    Font(String),
+    Theme(String),
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dependency::Binary(name) => write!(f, "{} (binary)", name),
+            Dependency::Font(name) => write!(f, "{} (font)", name),
+            Dependency::Theme(name) => write!(f, "{} (theme)", name),
+        }
+    }
+}
+
+impl Manifest {
+    // themes without a theme.toml are fine, they just don't declare anything
+    pub fn load(theme_path: &Path) -> Result<Option<Manifest>, String> {
+        let path = theme_path.join(MANIFEST_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        match toml::from_str::<Manifest>(&content) {
+            Ok(manifest) => Ok(Some(manifest)),
+            Err(e) => Err(format!("Failed to parse {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn hook(&self, hook: &str) -> Option<&str> {
+        let script = match hook {
+            "install" => &self.hooks.install,
+            "enable" => &self.hooks.enable,
+            "cleanup" => &self.hooks.cleanup,
+            _ => &None,
+        };
+        script.as_deref()
+    }
+}
+
+pub fn binary_exists(name: &str) -> bool {
+    if name.contains('/') {
+        return Path::new(name).is_file();
+    }
+    match std::env::var_os("PATH") {
+        Some(paths) => std::env::split_paths(&paths).any(|dir| dir.join(name).is_file()),
+        None => false,
+    }
+}
+
+// None when fontconfig isn't around to ask
+fn installed_fonts() -> Option<Vec<String>> {
+    let output = std::process::Command::new("fc-list").arg(":").arg("family").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .flat_map(|line| line.split(','))
+            .map(|family| family.trim().to_lowercase())
+            .collect()
+    )
+}
+
+impl Dependencies {
+    pub fn missing(&self, theme_dir: &Path) -> Vec<Dependency> {
+        let mut missing = Vec::new();
+
+        for binary in &self.binaries {
+            if !binary_exists(binary) {
+                missing.push(Dependency::Binary(binary.clone()));
+            }
+        }
+
+        if !self.fonts.is_empty() {
+            if let Some(installed) = installed_fonts() {
+                for font in &self.fonts {
+                    if !installed.contains(&font.to_lowercase()) {
+                        missing.push(Dependency::Font(font.clone()));
+                    }
+                }
+            }
+        }
+
+        for theme in &self.themes {
+            if !theme_installed(theme, theme_dir) {
+                missing.push(Dependency::Theme(theme.clone()));
+            }
+        }
+
+        missing
+    }
+}
@@ -1,4 +1,17 @@
 pub mod theme;
 pub mod ansi;
 pub mod repo;
-pub mod config;
\ No newline at end of file
+pub mod config;
+pub mod cache;
+pub mod settings;
+pub mod git;
+pub mod lock;
+pub mod manifest;
+pub mod parser;
+pub mod history;
+pub mod hyprland;
+pub mod search;
+pub mod output;
+pub mod preview;
+pub mod uri;
+pub mod doctor;
\ No newline at end of file
@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::util::ansi::{self, ERROR, WARNING};
+use crate::util::doctor::Check;
+use crate::util::settings::Registry;
+use crate::util::theme::Theme;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_format(format: Format) {
+    JSON.store(format == Format::Json, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+// progress and status messages, kept off stdout when it has to stay parseable
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::util::output::is_json() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+// part of the json output, don't rename variants
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    Usage,
+    Settings,
+    Config,
+    Registry,
+    NotFound,
+    Install,
+    Uninstall,
+    Update,
+    Sync,
+    Dependencies,
+    Hook,
+    Apply,
+    History,
+    Uri,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: ErrorCode,
+    message: &'a str,
+}
+
+#[derive(Serialize)]
+struct ErrorDocument<'a> {
+    error: ErrorBody<'a>,
+}
+
+pub fn print<T: Serialize>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("{}", ansi::on_stderr(ERROR, e)),
+    }
+}
+
+// report an error and hand back the exit code to return with
+pub fn fail(code: ErrorCode, message: impl Display) -> ExitCode {
+    let message = message.to_string();
+    if is_json() {
+        print(&ErrorDocument { error: ErrorBody { code, message: &message } });
+    } else {
+        eprintln!("{}", ansi::on_stderr(ERROR, message));
+    }
+    ExitCode::FAILURE
+}
+
+// one of several themes failed, json output carries the error in its document instead
+pub fn item_failed(name: &str, message: impl Display) {
+    if !is_json() {
+        eprintln!("{}", ansi::on_stderr(ERROR, format!("{}: {}", name, message)));
+    }
+}
+
+// something that doesn't stop the command, always on stderr so json output stays parseable
+pub fn warn(message: impl Display) {
+    eprintln!("{}", ansi::on_stderr(WARNING, message));
+}
+
+// what a command that doesn't return any data did
+#[derive(Serialize)]
+pub struct Done {
+    pub action: &'static str,
+    pub theme: Option<String>,
+    pub message: String,
+}
+
+pub fn done(action: &'static str, theme: Option<&str>, message: impl Display) {
+    if is_json() {
+        print(&Done { action, theme: theme.map(|theme| theme.to_string()), message: message.to_string() });
+    } else {
+        println!("{}", message);
+    }
+}
+
+#[derive(Serialize)]
+pub struct ListedTheme {
+    #[serde(flatten)]
+    pub theme: Theme,
+    pub author: String,
+    pub enabled: bool,
+    // only filled in by `list --updates`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updates: Option<usize>,
+    // why `list --updates` couldn't check the theme
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ThemeList {
+    pub themes: Vec<ListedTheme>,
+}
+
+#[derive(Serialize)]
+pub struct ComponentState {
+    pub name: String,
+    pub description: Option<String>,
+    pub enabled: bool,
+}
+
+#[derive(Serialize)]
+pub struct ComponentList {
+    pub theme: String,
+    pub components: Vec<ComponentState>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryVersion {
+    pub version: usize,
+    pub themes: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryList {
+    pub versions: Vec<HistoryVersion>,
+}
+
+#[derive(Serialize)]
+pub struct RegistryList<'a> {
+    pub registries: &'a [Registry],
+}
+
+#[derive(Serialize)]
+pub struct SyncedTheme {
+    pub name: String,
+    // up_to_date, installed, updated, removed, would_remove (--dry-run), kept (pruning declined) or failed
+    pub status: &'static str,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SyncReport {
+    pub themes: Vec<SyncedTheme>,
+}
+
+#[derive(Serialize)]
+pub struct EnabledTheme {
+    pub name: String,
+    pub path: PathBuf,
+    pub installed: bool,
+    pub commit: Option<String>,
+    pub components: Vec<String>,
+    pub variables: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+pub struct HyprlandStatus {
+    pub socket: PathBuf,
+    pub reachable: bool,
+    pub errors: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct Status {
+    pub config: PathBuf,
+    pub enabled: Vec<EnabledTheme>,
+    // None outside of a hyprland session
+    pub hyprland: Option<HyprlandStatus>,
+}
+
+#[derive(Serialize)]
+pub struct ThemeInfo {
+    #[serde(flatten)]
+    pub theme: Theme,
+    pub author: String,
+    pub version: Option<String>,
+    pub installed: bool,
+    pub enabled: bool,
+    pub commit: Option<String>,
+    // tag or commit the theme is pinned to
+    pub pinned: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DoctorReport {
+    pub checks: Vec<Check>,
+    // false while any error is left unfixed
+    pub healthy: bool,
+}
@@ -0,0 +1,289 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+// the parts of hyprtheme.conf that hyprtheme writes, everything else belongs to the user
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    // `# modules:` and `# set:` lines
+    Header,
+    // `# variables` ... `# variables end`
+    Variables,
+    // `# import` ... `# import end`
+    Imports,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Line {
+    User(String),
+    Managed(Block),
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedModule {
+    pub name: String,
+    pub components: Option<Vec<String>>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Parsed {
+    pub modules: Vec<ParsedModule>,
+    pub variables: BTreeMap<String, BTreeMap<String, String>>,
+    pub layout: Vec<Line>,
+    pub warnings: Vec<ParseError>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(line: usize, message: String) -> ParseError {
+        ParseError { line, message }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+enum State {
+    Outside,
+    Variables(usize),
+    Imports(usize),
+}
+
+// split the modules header on commas outside of `[...]` component lists
+fn split_modules(modules: &str, line: usize) -> Result<Vec<&str>, ParseError> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in modules.char_indices() {
+        match c {
+            '[' if depth == 0 => depth += 1,
+            ']' if depth == 1 => depth -= 1,
+            '[' | ']' => return Err(ParseError::new(line, format!("unexpected `{}` in modules list", c))),
+            ',' if depth == 0 => {
+                parts.push(&modules[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    if depth != 0 {
+        return Err(ParseError::new(line, String::from("unclosed `[` in modules list")));
+    }
+    parts.push(&modules[start..]);
+    Ok(parts)
+}
+
+fn parse_modules(modules: &str, line: usize, parsed: &mut Parsed) -> Result<(), ParseError> {
+    for module in split_modules(modules, line)? {
+        let module = module.trim();
+        if module.is_empty() {
+            continue;
+        }
+
+        // <theme>[<component>,...] keeps the user's component choice
+        let (name, components) = match module.split_once('[') {
+            Some((name, components)) => match components.strip_suffix(']') {
+                Some(components) => (name.trim(), Some(
+                    components.split(',').map(|c| c.trim()).filter(|c| !c.is_empty()).map(|c| c.to_string()).collect::<Vec<String>>()
+                )),
+                None => return Err(ParseError::new(line, format!("unexpected text after components of {}", name.trim()))),
+            },
+            None => (module, None),
+        };
+
+        if name.is_empty() || name.contains('/') || name == ".." {
+            return Err(ParseError::new(line, format!("invalid theme name `{}`", name)));
+        }
+        if parsed.modules.iter().any(|m| m.name == name) {
+            parsed.warnings.push(ParseError::new(line, format!("theme {} is listed twice, ignoring the second entry", name)));
+            continue;
+        }
+
+        parsed.modules.push(ParsedModule { name: name.to_string(), components, line });
+    }
+    Ok(())
+}
+
+// # set:<theme> <variable>=<value>
+fn parse_set(set: &str, line: usize, parsed: &mut Parsed) -> Result<(), ParseError> {
+    let malformed = || ParseError::new(line, String::from("expected `# set:<theme> <variable>=<value>`"));
+    let (theme, assignment) = set.trim_start().split_once(' ').ok_or_else(malformed)?;
+    let (variable, value) = assignment.split_once('=').ok_or_else(malformed)?;
+    if theme.is_empty() || variable.trim().is_empty() {
+        return Err(malformed());
+    }
+    parsed.variables.entry(theme.to_string()).or_default().insert(variable.trim().to_string(), value.to_string());
+    Ok(())
+}
+
+pub fn parse(content: &str) -> Result<Parsed, ParseError> {
+    let mut parsed = Parsed::default();
+    let mut state = State::Outside;
+    let mut seen = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let number = index + 1;
+        let trimmed = line.trim();
+
+        match state {
+            State::Outside => {
+                if let Some(modules) = line.strip_prefix("# modules:") {
+                    if seen.contains(&Block::Header) {
+                        return Err(ParseError::new(number, String::from("second `# modules:` line")));
+                    }
+                    parse_modules(modules, number, &mut parsed)?;
+                    parsed.layout.push(Line::Managed(Block::Header));
+                    seen.push(Block::Header);
+                } else if let Some(set) = line.strip_prefix("# set:") {
+                    parse_set(set, number, &mut parsed)?;
+                } else if trimmed == "# variables" {
+                    if seen.contains(&Block::Variables) {
+                        return Err(ParseError::new(number, String::from("second `# variables` block")));
+                    }
+                    state = State::Variables(number);
+                } else if trimmed == "# import" {
+                    if seen.contains(&Block::Imports) {
+                        return Err(ParseError::new(number, String::from("second `# import` block")));
+                    }
+                    state = State::Imports(number);
+                } else if trimmed == "# variables end" || trimmed == "# import end" {
+                    return Err(ParseError::new(number, format!("`{}` without a matching start", trimmed)));
+                } else {
+                    parsed.layout.push(Line::User(line.to_string()));
+                }
+            },
+            State::Variables(start) => {
+                if trimmed == "# variables end" {
+                    parsed.layout.push(Line::Managed(Block::Variables));
+                    seen.push(Block::Variables);
+                    state = State::Outside;
+                } else if trimmed == "# import" || trimmed.starts_with("# modules:") {
+                    return Err(ParseError::new(start, String::from("`# variables` block is never closed with `# variables end`")));
+                } else if !trimmed.is_empty() && !trimmed.starts_with('$') {
+                    parsed.warnings.push(ParseError::new(number, format!("`{}` is inside the managed variables block and will be overwritten", trimmed)));
+                }
+            },
+            State::Imports(start) => {
+                if trimmed == "# import end" {
+                    parsed.layout.push(Line::Managed(Block::Imports));
+                    seen.push(Block::Imports);
+                    state = State::Outside;
+                } else if trimmed == "# variables" || trimmed.starts_with("# modules:") {
+                    return Err(ParseError::new(start, String::from("`# import` block is never closed with `# import end`")));
+                } else if !trimmed.is_empty() && !trimmed.starts_with("source=") {
+                    parsed.warnings.push(ParseError::new(number, format!("`{}` is inside the managed import block and will be overwritten", trimmed)));
+                }
+            },
+        }
+    }
+
+    match state {
+        State::Outside => Ok(parsed),
+        State::Variables(start) => Err(ParseError::new(start, String::from("`# variables` block is never closed with `# variables end`"))),
+        State::Imports(start) => Err(ParseError::new(start, String::from("`# import` block is never closed with `# import end`"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::config::Config;
+    use crate::util::settings::Settings;
+
+    const CONFIG: &str = "\
+# my own notes
+# modules:nord[bar,launcher],gone
+# set:nord accent=rgb(ff0000)
+$terminal = kitty
+
+# variables
+$nord=/old/path
+# variables end
+
+bind = SUPER, Q, exec, $terminal
+# import
+source=/old/path/theme.conf
+# import end
+# trailing user line
+";
+
+    fn user_lines(parsed: &Parsed) -> Vec<&str> {
+        parsed.layout.iter().filter_map(|line| match line {
+            Line::User(line) => Some(line.as_str()),
+            Line::Managed(_) => None,
+        }).collect()
+    }
+
+    async fn load_in(dir: &tempfile::TempDir, content: &str) -> Config {
+        std::fs::write(dir.path().join("hyprtheme.conf"), content).unwrap();
+        let settings: Settings = toml::from_str(&format!("theme_dir = {:?}", dir.path())).unwrap();
+        Config::from(&settings).await.unwrap()
+    }
+
+    async fn load(content: &str) -> (tempfile::TempDir, Config) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("nord")).unwrap();
+        std::fs::write(dir.path().join("nord/theme.conf"), "").unwrap();
+        let config = load_in(&dir, content).await;
+        (dir, config)
+    }
+
+    #[tokio::test]
+    async fn build_keeps_user_lines() {
+        let (dir, mut config) = load(CONFIG).await;
+        let built = config.build();
+        let reparsed = parse(&built).unwrap();
+        let original = parse(CONFIG).unwrap();
+
+        assert_eq!(user_lines(&reparsed), user_lines(&original));
+        assert_eq!(reparsed.layout, original.layout);
+        assert_eq!(reparsed.variables, original.variables);
+        let modules = |parsed: &Parsed| parsed.modules.iter().map(|m| (m.name.clone(), m.components.clone())).collect::<Vec<_>>();
+        assert_eq!(modules(&reparsed), modules(&original));
+        assert!(built.contains(&format!("source={}", dir.path().join("nord/theme.conf").display())), "{}", built);
+
+        // building what was built changes nothing
+        assert_eq!(load_in(&dir, &built).await.build(), built);
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        let cases = [
+            ("# modules:a\n\n# modules:b\n", 3),
+            ("x\n# variables\n$a=b\n", 2),
+            ("x\ny\n# import\nsource=a\n# variables\n", 3),
+            ("# import end\n", 1),
+            ("\n# modules:a[bar\n", 2),
+            ("\n\n\n# set:nord\n", 4),
+        ];
+        for (content, line) in cases {
+            let error = parse(content).unwrap_err();
+            assert_eq!(error.line, line, "{:?}", content);
+            assert!(error.to_string().starts_with(&format!("line {}: ", line)), "{}", error);
+        }
+
+        let parsed = parse("# modules:a,a\n# import\nbind = x\n# import end\n").unwrap();
+        let lines = parsed.warnings.iter().map(|warning| warning.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn missing_themes_stay_enabled() {
+        let (dir, mut config) = load(CONFIG).await;
+        let names = config.modules.iter().map(|module| module.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["nord", "gone"]);
+        assert!(config.warnings.iter().any(|warning| warning.contains("line 2: theme gone is enabled")), "{:?}", config.warnings);
+
+        let built = config.build();
+        assert!(built.contains("# modules:nord[bar,launcher],gone,"), "{}", built);
+        assert!(!built.contains(&dir.path().join("gone").display().to_string()), "{}", built);
+    }
+}
@@ -0,0 +1,251 @@
+use std::io::Cursor;
+
+use base64::Engine;
+use clap::ValueEnum;
+use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageFormat, RgbaImage};
+
+use crate::util::ansi::{reset, rgb, rgb_bg};
+
+// rough size of a terminal cell in pixels, only used to size sixel output
+const CELL_WIDTH: u32 = 8;
+const CELL_HEIGHT: u32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Protocol {
+    Auto,
+    Kitty,
+    Sixel,
+    Halfblock,
+    None,
+}
+
+// what the terminal we're running in can most likely show, there's no reliable way to ask without a round trip
+pub fn detect() -> Protocol {
+    let var = |name: &str| std::env::var(name).unwrap_or_default().to_lowercase();
+    let term = var("TERM");
+    let program = var("TERM_PROGRAM");
+
+    if !var("KITTY_WINDOW_ID").is_empty() || term.contains("kitty") || ["wezterm", "ghostty"].contains(&program.as_str()) {
+        return Protocol::Kitty;
+    }
+    if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") || program == "contour" {
+        return Protocol::Sixel;
+    }
+    Protocol::Halfblock
+}
+
+pub fn decode(bytes: &[u8]) -> Result<DynamicImage, String> {
+    image::load_from_memory(bytes).map_err(|e| format!("Failed to decode preview image: {}", e))
+}
+
+// fit `width`x`height` into `max_width`x`max_height` keeping the aspect ratio, never scaling up
+fn fit(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    let scale = (max_width as f64 / width as f64).min(max_height as f64 / height as f64).min(1.0);
+    (((width as f64 * scale) as u32).max(1), ((height as f64 * scale) as u32).max(1))
+}
+
+// escape sequence that draws `bytes` in at most `columns`x`rows` cells
+pub fn render(bytes: &[u8], protocol: Protocol, columns: u16, rows: u16) -> Result<String, String> {
+    let image = decode(bytes)?;
+    match protocol {
+        Protocol::Auto => render(bytes, detect(), columns, rows),
+        Protocol::Kitty => kitty(&image, columns, rows),
+        Protocol::Sixel => Ok(sixel(&image, columns, rows)),
+        Protocol::Halfblock => Ok(halfblock(&image, columns, rows)),
+        Protocol::None => Ok(String::new()),
+    }
+}
+
+fn kitty(image: &DynamicImage, columns: u16, rows: u16) -> Result<String, String> {
+    let mut png = Vec::new();
+    if let Err(e) = image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png) {
+        return Err(format!("Failed to encode preview image: {}", e));
+    }
+    let encoded = base64::engine::general_purpose::STANDARD.encode(&png);
+
+    // let the terminal scale it, giving only one of the cell counts keeps the aspect ratio
+    let (width, height) = image.dimensions();
+    let size = match (width as f64 / columns as f64) < (height as f64 / (rows as f64 * 2.0)) {
+        true => format!("r={}", rows),
+        false => format!("c={}", columns),
+    };
+
+    Ok(kitty_chunks(&encoded, &size))
+}
+
+// payloads are sent in chunks of at most 4096 bytes, m=1 means more are coming
+fn kitty_chunks(encoded: &str, size: &str) -> String {
+    let chunks = encoded.as_bytes().chunks(4096).collect::<Vec<&[u8]>>();
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = if i + 1 < chunks.len() { 1 } else { 0 };
+        let chunk = String::from_utf8_lossy(chunk);
+        if i == 0 {
+            out.push_str(&format!("\x1b_Ga=T,f=100,q=2,{},m={};{}\x1b\\", size, more, chunk));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+        }
+    }
+    out.push('\n');
+    out
+}
+
+// index into a 6x6x6 color cube, good enough for a thumbnail
+fn palette_index(r: u8, g: u8, b: u8) -> usize {
+    let level = |c: u8| (c as usize * 5 + 127) / 255;
+    level(r) * 36 + level(g) * 6 + level(b)
+}
+
+fn sixel(image: &DynamicImage, columns: u16, rows: u16) -> String {
+    let (width, height) = fit(image.width(), image.height(), columns as u32 * CELL_WIDTH, rows as u32 * CELL_HEIGHT);
+    let image = image.resize_exact(width, height, FilterType::Triangle).to_rgba8();
+
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+    for index in 0..216 {
+        // sixel colors are percentages
+        let percent = |level: usize| level * 100 / 5;
+        out.push_str(&format!("#{};2;{};{};{}", index, percent(index / 36), percent(index / 6 % 6), percent(index % 6)));
+    }
+
+    let pixels = image.pixels()
+        .map(|pixel| match pixel[3] < 128 {
+            true => None,
+            false => Some(palette_index(pixel[0], pixel[1], pixel[2])),
+        })
+        .collect::<Vec<Option<usize>>>();
+
+    for band in (0..height).step_by(6) {
+        let band_rows = (height - band).min(6);
+        let mut colors = Vec::new();
+        for y in band..band + band_rows {
+            for x in 0..width {
+                if let Some(color) = pixels[(y * width + x) as usize] {
+                    if !colors.contains(&color) {
+                        colors.push(color);
+                    }
+                }
+            }
+        }
+
+        for color in colors {
+            out.push_str(&format!("#{}", color));
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width {
+                let mut bits = 0;
+                for row in 0..band_rows {
+                    if pixels[((band + row) * width + x) as usize] == Some(color) {
+                        bits |= 1 << row;
+                    }
+                }
+                let c = (63 + bits) as u8 as char;
+                run = match run {
+                    Some((previous, count)) if previous == c => Some((c, count + 1)),
+                    Some((previous, count)) => {
+                        push_run(&mut out, previous, count);
+                        Some((c, 1))
+                    },
+                    None => Some((c, 1)),
+                };
+            }
+            if let Some((c, count)) = run {
+                push_run(&mut out, c, count);
+            }
+            // back to the start of the band for the next color
+            out.push('$');
+        }
+        out.push('-');
+    }
+
+    out.push_str("\x1b\\\n");
+    out
+}
+
+fn push_run(out: &mut String, c: char, count: usize) {
+    if count > 3 {
+        out.push_str(&format!("!{}{}", count, c));
+    } else {
+        out.extend(std::iter::repeat_n(c, count));
+    }
+}
+
+// scaled to two pixels per cell, upper half in the foreground color and lower half in the background
+pub fn halfblock_image(image: &DynamicImage, columns: u16, rows: u16) -> RgbaImage {
+    let (width, height) = fit(image.width(), image.height(), columns as u32, rows as u32 * 2);
+    image.resize_exact(width, height, FilterType::Triangle).to_rgba8()
+}
+
+fn halfblock(image: &DynamicImage, columns: u16, rows: u16) -> String {
+    let image = halfblock_image(image, columns, rows);
+    let mut out = String::new();
+    for y in (0..image.height()).step_by(2) {
+        for x in 0..image.width() {
+            let top = image.get_pixel(x, y);
+            out.push_str(&rgb(top[0], top[1], top[2]));
+            if y + 1 < image.height() {
+                let bottom = image.get_pixel(x, y + 1);
+                out.push_str(&rgb_bg(bottom[0], bottom[1], bottom[2]));
+            }
+            out.push('▀');
+        }
+        out.push_str(&reset());
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn images_fit_the_cell_box() {
+        assert_eq!(fit(1600, 900, 80, 80), (80, 45));
+        assert_eq!(fit(900, 1600, 80, 80), (45, 80));
+        assert_eq!(fit(200, 100, 80, 20), (40, 20));
+        // small images aren't blown up, and nothing shrinks to zero
+        assert_eq!(fit(10, 10, 80, 80), (10, 10));
+        assert_eq!(fit(1000, 1, 10, 10), (10, 1));
+    }
+
+    #[test]
+    fn colors_land_in_the_cube() {
+        assert_eq!(palette_index(0, 0, 0), 0);
+        assert_eq!(palette_index(255, 255, 255), 215);
+        assert_eq!(palette_index(255, 0, 0), 180);
+        assert_eq!(palette_index(0, 255, 0), 30);
+        assert_eq!(palette_index(0, 0, 255), 5);
+        assert_eq!(palette_index(128, 128, 128), 3 * 36 + 3 * 6 + 3);
+    }
+
+    #[test]
+    fn sixel_runs_are_compressed() {
+        let mut out = String::new();
+        push_run(&mut out, '@', 1);
+        push_run(&mut out, '?', 3);
+        push_run(&mut out, '~', 4);
+        push_run(&mut out, 'A', 120);
+        assert_eq!(out, "@???!4~!120A");
+
+        let red = Rgba([255, 0, 0, 255]);
+        let clear = Rgba([0, 0, 0, 0]);
+        let row = [red, red, clear, red, red, red, red, red];
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 1, |x, _| row[x as usize]));
+        let out = sixel(&image, 10, 10);
+        assert!(out.starts_with("\x1bP0;1;0q\"1;1;8;1"), "{:?}", out);
+        // only red is drawn, the transparent pixel leaves a gap and the last five pixels are one run
+        assert!(out.ends_with("#180@@?!5@$-\x1b\\\n"), "{:?}", out);
+    }
+
+    #[test]
+    fn kitty_payloads_are_chunked() {
+        let encoded = "A".repeat(4097);
+        let out = kitty_chunks(&encoded, "c=40");
+        let first = format!("\x1b_Ga=T,f=100,q=2,c=40,m=1;{}\x1b\\", "A".repeat(4096));
+        assert_eq!(out, format!("{}\x1b_Gm=0;A\x1b\\\n", first));
+
+        let out = kitty_chunks(&"A".repeat(4096), "r=10");
+        assert_eq!(out, format!("\x1b_Ga=T,f=100,q=2,r=10,m=0;{}\x1b\\\n", "A".repeat(4096)));
+        assert_eq!(kitty_chunks(&"A".repeat(8192), "r=10").matches("m=1").count(), 1);
+    }
+}
@@ -1,28 +1,91 @@
-use std::{path::PathBuf, time::Duration};
+use std::{path::{Path, PathBuf}, time::Duration};
 
-use reqwest::Client;
+use reqwest::{header, Client, Response, StatusCode};
 use indicatif::{ProgressBar, ProgressStyle};
 
-use crate::util::theme::{Themes, Theme};
+use crate::util::cache::{CachedIndex, DEFAULT_TTL};
+use crate::util::git;
+use crate::util::lock::Lockfile;
+use crate::util::manifest::Manifest;
+use crate::util::output;
+use crate::util::search;
+use crate::util::settings::Registry;
+use crate::util::theme::{self, Themes, Theme};
 
-pub fn theme_installed(theme_name: &str, theme_dir: &PathBuf) -> bool {
-    let dir = theme_dir.join(theme_name);
-    if dir.exists() {
-        return true;
+pub fn theme_installed(theme_name: &str, theme_dir: &Path) -> bool {
+    theme_dir.join(theme_name).exists() || theme_dir.join(theme_name.to_lowercase()).exists()
+}
+
+fn parse_themes(text: &str, theme_dir: &Path, registry: &Registry) -> Result<Themes, String> {
+    match serde_json::from_str::<Themes>(text) {
+        Ok(mut themes) => {
+            for theme in &mut themes.themes {
+                theme._installed = Some(theme_installed(&theme.name, theme_dir));
+                theme._registry = Some(registry.name.clone());
+            }
+            Ok(themes)
+        },
+        Err(e) => Err(e.to_string()),
     }
+}
 
-    let dir = theme_dir.join(theme_name.to_lowercase());
-    if dir.exists() {
-        return true;
+fn header(res: &Response, name: header::HeaderName) -> Option<String> {
+    res.headers().get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string())
+}
+
+// `file://` urls and paths point at a themes.json or a directory containing one,
+// anything else has to exist to count as a path so `github.com/x/themes.json` isn't mistaken for one
+pub fn local_path(url: &str) -> Option<PathBuf> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return Some(PathBuf::from(path));
     }
+    if url.contains("://") {
+        return None;
+    }
+    let path = PathBuf::from(shellexpand::tilde(url).as_ref());
+    match is_local_path(url) || path.exists() {
+        true => Some(path),
+        false => None,
+    }
+}
 
-    return false
+fn read_local_registry(path: &Path, theme_dir: &Path, registry: &Registry) -> Result<Themes, String> {
+    let path = if path.is_dir() {
+        path.join("themes.json")
+    } else {
+        path.to_path_buf()
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(text) => parse_themes(&text, theme_dir, registry),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
 }
 
-pub async fn fetch_themes(theme_dir: &PathBuf, file_url:Option<&str>) -> Result<Themes, String> {
+pub async fn fetch_registry(theme_dir: &Path, registry: &Registry, offline: bool) -> Result<Themes, String> {
+    let url = registry.url.as_str();
+
+    // local registries are always available, no need to cache them
+    if let Some(path) = local_path(url) {
+        return read_local_registry(&path, theme_dir, registry);
+    }
+
+    let cached = CachedIndex::load(url);
+
+    if offline {
+        return match cached {
+            Some(cached) => parse_themes(&cached.body, theme_dir, registry),
+            None => Err(format!("No cached registry for {}, run once without --offline", url)),
+        };
+    }
+
+    if let Some(cached) = &cached {
+        if cached.is_fresh(DEFAULT_TTL) {
+            return parse_themes(&cached.body, theme_dir, registry);
+        }
+    }
+
     // fetch with progressbar
     let client = Client::new();
-    let url = file_url.unwrap_or("https://github.com/hyprland-community/theme-repo/blob/main/themes.json?raw=true");
     
     let progress_bar = ProgressBar::new_spinner();
 
@@ -33,46 +96,367 @@ pub async fn fetch_themes(theme_dir: &PathBuf, file_url:Option<&str>) -> Result<
 
     progress_bar.enable_steady_tick(Duration::from_millis(50));
 
-    progress_bar.set_message("Fetching themes");
-
-    match client.get(url).send().await {
-        Ok(res) => match res.text().await {
-            Ok(text) => {
-                
-                match serde_json::from_str::<Themes>(&text) {
-                    Ok(mut themes) => {
-                         for theme in &mut themes.themes {
-                              theme._installed = Some(theme_installed(&theme.name, &theme_dir));
-                         }
-                         progress_bar.finish_with_message(format!("Fetched {} themes", &themes.themes.len()));
-                         return Ok(themes)
-                    },
-                    Err(e) => Err(e.to_string()),
-                }
-    
-            },
-            Err(e) => {
-                progress_bar.finish_with_message("Failed to parse response");
-                return Err(e.to_string());
-            },
+    progress_bar.set_message(format!("Fetching themes from {}", registry.name));
+
+    // revalidate the cached copy instead of downloading it again
+    let mut request = client.get(url);
+    if let Some(cached) = &cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let res = match request.send().await.and_then(|res| res.error_for_status()) {
+        Ok(res) => res,
+        Err(e) => {
+            return match cached {
+                Some(cached) => {
+                    progress_bar.finish_with_message(format!("Failed to fetch themes, using cached registry ({}s old)", cached.age().as_secs()));
+                    parse_themes(&cached.body, theme_dir, registry)
+                },
+                None => {
+                    progress_bar.finish_with_message("Failed to fetch themes");
+                    Err(e.to_string())
+                },
+            };
+        },
+    };
+
+    if res.status() == StatusCode::NOT_MODIFIED {
+        if let Some(mut cached) = cached {
+            // a stale timestamp only costs another revalidation, so this is not fatal
+            let _ = cached.touch();
+            let themes = parse_themes(&cached.body, theme_dir, registry)?;
+            progress_bar.finish_with_message(format!("Fetched {} themes (unchanged)", &themes.themes.len()));
+            return Ok(themes);
+        }
+    }
+
+    let etag = header(&res, header::ETAG);
+    let last_modified = header(&res, header::LAST_MODIFIED);
+
+    match res.text().await {
+        Ok(text) => {
+            let themes = parse_themes(&text, theme_dir, registry)?;
+            if let Err(e) = CachedIndex::new(url, text, etag, last_modified).save() {
+                eprintln!("{}", e);
+            }
+            progress_bar.finish_with_message(format!("Fetched {} themes", &themes.themes.len()));
+            Ok(themes)
         },
         Err(e) => {
-            progress_bar.finish_with_message("Failed to fetch themes");
-            return Err(e.to_string());
+            progress_bar.finish_with_message("Failed to parse response");
+            Err(e.to_string())
         },
     }
 }
 
-pub async fn find_theme(theme_name: &str, theme_dir: &PathBuf) -> Result<Theme, String> {
-    let themes = match fetch_themes(theme_dir,None).await {
-        Ok(themes) => themes,
-        Err(e) => return Err(e),
+pub async fn fetch_themes(theme_dir: &Path, registries: &[Registry], offline: bool) -> Result<Themes, String> {
+    let mut themes = Themes { themes: Vec::new() };
+    let mut errors = Vec::new();
+
+    for registry in registries {
+        let fetched = match fetch_registry(theme_dir, registry, offline).await {
+            Ok(fetched) => fetched,
+            Err(e) if offline => {
+                errors.push(format!("{}: {}", registry.name, e));
+                continue;
+            },
+            // e.g. the server answered with something that isn't a registry
+            Err(e) => match fetch_registry(theme_dir, registry, true).await {
+                Ok(fetched) => fetched,
+                Err(_) => {
+                    errors.push(format!("{}: {}", registry.name, e));
+                    continue;
+                },
+            },
+        };
+        themes.themes.extend(fetched.themes);
+    }
+
+    if themes.themes.is_empty() && !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    for e in errors {
+        output::warn(e);
+    }
+    Ok(themes)
+}
+
+fn matches_qualifier(theme: &Theme, qualifier: &str) -> bool {
+    let qualifier = qualifier.to_lowercase();
+    match &theme._registry {
+        Some(registry) if registry.to_lowercase() == qualifier => true,
+        _ => theme.get_author().to_lowercase() == qualifier,
+    }
+}
+
+// accepts `name`, `<registry>/name` or `<author>/name`
+pub async fn find_theme(theme_name: &str, theme_dir: &Path, registries: &[Registry], offline: bool) -> Result<Theme, String> {
+    let themes = fetch_themes(theme_dir, registries, offline).await?;
+
+    let (qualifier, name) = match theme_name.split_once('/') {
+        Some((qualifier, name)) => (Some(qualifier), name),
+        None => (None, theme_name),
     };
-    for theme in themes.themes {
-        if theme.name.to_lowercase() == theme_name.to_lowercase() {
-            return Ok(theme);
+
+    let mut found = themes.themes.iter().filter(|theme| {
+        theme.name.to_lowercase() == name.to_lowercase()
+            && qualifier.is_none_or(|q| matches_qualifier(theme, q))
+    }).cloned().collect::<Vec<Theme>>();
+
+    match found.len() {
+        0 => {
+            let suggestions = search::suggest(&themes.themes, name);
+            match suggestions.is_empty() {
+                true => Err(format!("Theme {} not found", name)),
+                false => Err(format!("Theme {} not found, did you mean {}?", name, suggestions.join(", "))),
+            }
+        },
+        1 => Ok(found.remove(0)),
+        _ => Err(format!(
+            "Theme {} exists in several registries, use one of: {}",
+            name,
+            found.iter().map(|theme| theme.qualified_name()).collect::<Vec<String>>().join(", ")
+        )),
+    }
+}
+
+// a url with a scheme or the scp-like `user@host:owner/repo`, a bare `foo.git` is still a registry name
+fn is_git_url(source: &str) -> bool {
+    if ["http://", "https://", "git://", "ssh://", "file://"].iter().any(|prefix| source.starts_with(prefix)) {
+        return true;
+    }
+    match source.split_once('@') {
+        Some((user, rest)) => !user.is_empty() && !user.contains('/') && rest.split_once(':').is_some_and(|(host, path)| {
+            !host.is_empty() && !host.contains('/') && !path.is_empty()
+        }),
+        None => false,
+    }
+}
+
+fn is_local_path(source: &str) -> bool {
+    source == "." || ["./", "../", "/", "~"].iter().any(|prefix| source.starts_with(prefix))
+}
+
+// split `name@rev`, ignoring the `@` in `git@host:owner/repo` style urls and in paths that exist
+pub fn split_rev(source: &str) -> (&str, Option<&str>) {
+    if Path::new(shellexpand::tilde(source).as_ref()).exists() {
+        return (source, None);
+    }
+    match source.rsplit_once('@') {
+        Some((name, rev)) if !name.is_empty() && !rev.is_empty() && !rev.contains(['/', ':']) => (name, Some(rev)),
+        _ => (source, None),
+    }
+}
+
+// `<git-url>[#branch]` or a path to a theme directory, anything else is a registry name
+pub fn theme_from_source(source: &str) -> Result<Option<Theme>, String> {
+    if is_git_url(source) {
+        let (url, branch) = match source.rsplit_once('#') {
+            Some((url, branch)) => (url, branch),
+            None => (source, ""),
+        };
+        let name = url.trim_end_matches('/').rsplit(['/', ':']).next().unwrap_or(url);
+        let name = name.strip_suffix(".git").unwrap_or(name);
+        if name.is_empty() {
+            return Err(format!("Can't derive a theme name from {}", url));
+        }
+        return Ok(Some(Theme::new(name, url, branch)));
+    }
+
+    if is_local_path(source) {
+        let path = PathBuf::from(shellexpand::tilde(source).as_ref());
+        let path = match path.canonicalize() {
+            Ok(path) => path,
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+        if !path.is_dir() {
+            return Err(format!("{} is not a directory", path.display()));
         }
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => return Err(format!("Can't derive a theme name from {}", path.display())),
+        };
+        return Ok(Some(Theme::new(&name, &path.display().to_string(), "")));
+    }
+
+    Ok(None)
+}
+
+// themes installed from a url or path aren't in any registry, but can still be managed by name
+pub fn find_installed(theme_name: &str, theme_dir: &Path) -> Option<Theme> {
+    let standardized = theme_name.to_lowercase().replace(' ', "_");
+    if theme::check_name(&standardized).is_ok() && theme_dir.join(&standardized).is_dir() {
+        Some(Theme::new(&standardized, "", ""))
+    } else {
+        None
     }
-    Err("Theme not found".to_string())
 }
 
+// everything in the theme dir, described from theme.toml, hyprtheme.lock and the git remote without touching the network
+pub fn installed_themes(theme_dir: &Path) -> Result<Vec<Theme>, String> {
+    let entries = match std::fs::read_dir(theme_dir) {
+        Ok(entries) => entries,
+        Err(e) => return Err(format!("Failed to read {}: {}", theme_dir.display(), e)),
+    };
+    let lockfile = Lockfile::load(theme_dir).ok();
+
+    let mut themes = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !entry.path().is_dir() || name.starts_with('.') {
+            continue;
+        }
+
+        let mut theme = Theme::new(&name, "", "");
+        theme._installed = Some(true);
+
+        match lockfile.as_ref().and_then(|lockfile| lockfile.get(&name)) {
+            Some(locked) => {
+                theme.repo = locked.repo.clone();
+                theme.branch = locked.branch.clone();
+                theme._registry = locked.registry.clone();
+            },
+            None => {
+                if let Ok(repository) = git::open(&entry.path()) {
+                    theme.repo = git::remote_url(&repository).unwrap_or_default();
+                    theme.branch = git::head_branch(&repository).unwrap_or_default();
+                }
+            },
+        }
+
+        if let Ok(Some(manifest)) = Manifest::load(&entry.path()) {
+            theme.desc = manifest.description.unwrap_or_default();
+            theme.tags = manifest.tags;
+            theme.images = manifest.images;
+            if let Some(config) = manifest.config {
+                theme.config = config;
+            }
+        }
+
+        themes.push(theme);
+    }
+
+    themes.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(themes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/themes.json"));
+
+    // a registry directory holding the fixture and a theme dir with one of its themes installed
+    fn setup() -> (tempfile::TempDir, PathBuf, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let registry_dir = dir.path().join("registry");
+        let theme_dir = dir.path().join("themes");
+        std::fs::create_dir_all(&registry_dir).unwrap();
+        std::fs::create_dir_all(theme_dir.join("installed")).unwrap();
+        std::fs::write(registry_dir.join("themes.json"), FIXTURE).unwrap();
+        (dir, registry_dir, theme_dir)
+    }
+
+    // what a remote registry serving the fixture would produce
+    fn remote(theme_dir: &Path, registry: &Registry) -> serde_json::Value {
+        serde_json::to_value(parse_themes(FIXTURE, theme_dir, registry).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn local_registries_match_remote() {
+        let (_dir, registry_dir, theme_dir) = setup();
+        let urls = [
+            registry_dir.display().to_string(),
+            registry_dir.join("themes.json").display().to_string(),
+            format!("file://{}", registry_dir.display()),
+            format!("file://{}", registry_dir.join("themes.json").display()),
+        ];
+        for url in urls {
+            let registry = Registry::new("local", &url);
+            let themes = fetch_registry(&theme_dir, &registry, false).await.unwrap();
+            assert_eq!(serde_json::to_value(&themes).unwrap(), remote(&theme_dir, &registry), "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn local_registry_marks_installed() {
+        let (_dir, registry_dir, theme_dir) = setup();
+        let registry = Registry::new("local", &format!("file://{}", registry_dir.display()));
+        let themes = fetch_registry(&theme_dir, &registry, true).await.unwrap();
+
+        let installed = themes.themes.iter().map(|theme| (theme.name.as_str(), theme._installed)).collect::<Vec<_>>();
+        assert_eq!(installed, vec![("Installed", Some(true)), ("available", Some(false))]);
+        assert!(themes.themes.iter().all(|theme| theme._registry.as_deref() == Some("local")));
+        assert_eq!(themes.themes[0].tags, vec!["dark"]);
+        assert!(themes.themes[1].tags.is_empty());
+    }
+
+    #[test]
+    fn missing_local_registry_is_an_error() {
+        let (_dir, registry_dir, theme_dir) = setup();
+        let path = registry_dir.join("missing.json");
+        let result = read_local_registry(&path, &theme_dir, &Registry::new("local", ""));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn only_paths_are_local() {
+        let (_dir, registry_dir, _) = setup();
+        assert_eq!(local_path("file:///tmp/themes.json"), Some(PathBuf::from("/tmp/themes.json")));
+        assert_eq!(local_path("/does/not/exist.json"), Some(PathBuf::from("/does/not/exist.json")));
+        assert_eq!(local_path("./themes.json"), Some(PathBuf::from("./themes.json")));
+        assert_eq!(local_path(&registry_dir.display().to_string()), Some(registry_dir.clone()));
+        assert_eq!(local_path("https://example.com/themes.json"), None);
+        assert_eq!(local_path("github.com/x/themes.json"), None);
+    }
+
+    #[test]
+    fn git_urls_need_a_scheme_or_host() {
+        assert!(is_git_url("https://github.com/x/theme"));
+        assert!(is_git_url("git@github.com:x/theme.git"));
+        assert!(is_git_url("file:///srv/theme.git"));
+        assert!(!is_git_url("foo.git"));
+        assert!(!is_git_url("registry/foo.git"));
+        assert!(!is_git_url("theme@v1.0"));
+    }
+
+    #[test]
+    fn existing_paths_keep_their_at() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("my@theme");
+        std::fs::create_dir_all(&path).unwrap();
+        let path = path.display().to_string();
+
+        assert_eq!(split_rev(&path), (path.as_str(), None));
+        assert_eq!(split_rev("theme@v1.0"), ("theme", Some("v1.0")));
+        assert_eq!(split_rev("git@github.com:x/theme"), ("git@github.com:x/theme", None));
+        assert_eq!(split_rev("git@github.com:x/theme@abc123"), ("git@github.com:x/theme", Some("abc123")));
+    }
+
+    #[test]
+    fn installed_names_stay_inside_the_theme_dir() {
+        let (dir, _, theme_dir) = setup();
+        std::fs::write(dir.path().join("hyprland.conf"), "").unwrap();
+
+        assert_eq!(find_installed("Installed", &theme_dir).map(|theme| theme.name), Some(String::from("installed")));
+        for name in ["", ".", "..", "../themes", "installed/..", "..\\x", ".hidden"] {
+            assert!(find_installed(name, &theme_dir).is_none(), "{:?}", name);
+        }
+
+        // registry names are standardized and checked before anything is touched
+        for name in ["..", "../registry", "a/b"] {
+            let theme = Theme::new(name, "", "");
+            assert!(theme.uninstall(theme_dir.clone()).is_err(), "{:?}", name);
+            assert!(theme.update(theme_dir.clone(), None, true).is_err(), "{:?}", name);
+            assert!(theme.install(theme_dir.clone(), None, false, false).is_err(), "{:?}", name);
+        }
+        assert!(dir.path().join("hyprland.conf").exists());
+        assert!(dir.path().join("registry").is_dir());
+        assert!(theme_dir.join("installed").is_dir());
+    }
+}
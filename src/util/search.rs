@@ -0,0 +1,185 @@
+use crate::util::theme::Theme;
+
+// how much a hit in each field counts, a name match beats a word buried in a description
+const NAME_WEIGHT: u32 = 3;
+const TAG_WEIGHT: u32 = 2;
+const AUTHOR_WEIGHT: u32 = 2;
+const DESC_WEIGHT: u32 = 1;
+
+// edit distance that counts swapping two neighbouring letters as one typo
+fn distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<char>>();
+    let b = b.chars().collect::<Vec<char>>();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+// one typo per four letters, short queries have to be spelled right
+fn allowed_typos(query: &str) -> usize {
+    query.chars().count() / 4
+}
+
+// every query character shows up in order, fewer gaps between them score higher
+fn subsequence(field: &str, query: &str) -> Option<u32> {
+    let mut chars = field.chars();
+    let mut gaps = 0;
+    for q in query.chars() {
+        let mut skipped = 0;
+        loop {
+            match chars.next() {
+                Some(c) if c == q => break,
+                Some(_) => skipped += 1,
+                None => return None,
+            }
+        }
+        gaps += skipped;
+    }
+    // letters spread over a whole sentence aren't a match
+    match 30u32.checked_sub(gaps) {
+        Some(score) if score >= 10 => Some(score),
+        _ => None,
+    }
+}
+
+// small typos in one of the field's words, e.g. `catpuccin` for `catppuccin`
+fn typo(field: &str, query: &str) -> Option<u32> {
+    let allowed = allowed_typos(query);
+    field
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| distance(word, query))
+        .filter(|distance| *distance <= allowed)
+        .min()
+        .map(|distance| 40 - 10 * distance.min(3) as u32)
+}
+
+// `scattered` allows the query's letters to be spread out, only for short fields like names
+fn field_score(field: &str, query: &str, scattered: bool) -> Option<u32> {
+    let field = field.to_lowercase();
+    if field.is_empty() {
+        return None;
+    }
+    if field == query {
+        return Some(100);
+    }
+    if field.starts_with(query) {
+        return Some(80);
+    }
+    if field.contains(query) {
+        return Some(60);
+    }
+    let words = query.split_whitespace().collect::<Vec<&str>>();
+    if words.len() > 1 && words.iter().all(|word| field.contains(word)) {
+        return Some(50);
+    }
+    typo(&field, query).or_else(|| subsequence(&field, query).filter(|_| scattered))
+}
+
+// None when the theme has nothing to do with the query
+pub fn score(theme: &Theme, query: &str) -> Option<u32> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let mut scores = vec![
+        field_score(&theme.name, &query, true).map(|score| score * NAME_WEIGHT),
+        field_score(&theme.get_author(), &query, true).map(|score| score * AUTHOR_WEIGHT),
+        field_score(&theme.desc, &query, false).map(|score| score * DESC_WEIGHT),
+    ];
+    for tag in &theme.tags {
+        scores.push(field_score(tag, &query, false).map(|score| score * TAG_WEIGHT));
+    }
+    scores.into_iter().flatten().max()
+}
+
+// matching themes, best first
+pub fn search(themes: Vec<Theme>, query: &str) -> Vec<Theme> {
+    let mut matches = themes
+        .into_iter()
+        .filter_map(|theme| score(&theme, query).map(|score| (score, theme)))
+        .collect::<Vec<(u32, Theme)>>();
+    matches.sort_by(|(a_score, a), (b_score, b)| b_score.cmp(a_score).then_with(|| a.name.cmp(&b.name)));
+    matches.into_iter().map(|(_, theme)| theme).collect()
+}
+
+// names close enough to `name` to be what the user meant
+pub fn suggest(themes: &[Theme], name: &str) -> Vec<String> {
+    let name = name.trim().to_lowercase();
+    let allowed = allowed_typos(&name);
+    let mut suggestions = themes
+        .iter()
+        .filter_map(|theme| {
+            let theme_name = theme.name.to_lowercase();
+            let distance = distance(&theme_name, &name);
+            // a typo in one word of a longer name
+            let distance = match typo(&theme_name, &name) {
+                Some(score) => distance.min(((40 - score) / 10) as usize),
+                None => distance,
+            };
+            if distance > allowed {
+                return None;
+            }
+            Some((distance, theme.name.clone()))
+        })
+        .collect::<Vec<(usize, String)>>();
+    suggestions.sort();
+    suggestions.dedup_by(|(_, a), (_, b)| a == b);
+    suggestions.into_iter().take(3).map(|(_, name)| name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn theme(name: &str, desc: &str, tags: &[&str]) -> Theme {
+        let mut theme = Theme::new(name, "https://github.com/someone/repo", "main");
+        theme.desc = desc.to_string();
+        theme.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        theme
+    }
+
+    fn names(themes: Vec<Theme>) -> Vec<String> {
+        themes.into_iter().map(|theme| theme.name).collect()
+    }
+
+    #[test]
+    fn short_queries_need_a_real_match() {
+        let themes = vec![
+            theme("Nord", "an arctic, north-bluish color palette", &["dark", "minimal"]),
+            theme("Catppuccin", "soothing pastel theme for hyprland", &["pastel"]),
+        ];
+        // every letter is somewhere in both descriptions, in order
+        assert!(names(search(themes.clone(), "ntl")).is_empty());
+        assert!(names(search(themes.clone(), "dml")).is_empty());
+        assert_eq!(names(search(themes.clone(), "pastel")), vec!["Catppuccin"]);
+        assert_eq!(names(search(themes.clone(), "arctic")), vec!["Nord"]);
+        assert_eq!(names(search(themes.clone(), "catpuccin")), vec!["Catppuccin"]);
+        assert_eq!(names(search(themes, "cpcn")), vec!["Catppuccin"]);
+    }
+
+    #[test]
+    fn suggestions_are_close_names() {
+        let themes = vec![theme("Nord", "", &[]), theme("Nordic", "", &[]), theme("Catppuccin Mocha", "", &[])];
+        assert!(suggest(&themes, "n").is_empty());
+        assert!(suggest(&themes, "no").is_empty());
+        assert_eq!(suggest(&themes, "nrod"), vec!["Nord"]);
+        assert_eq!(suggest(&themes, "nordik"), vec!["Nordic"]);
+        assert_eq!(suggest(&themes, "catpuccin"), vec!["Catppuccin Mocha"]);
+    }
+}
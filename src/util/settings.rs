@@ -0,0 +1,222 @@
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use expanduser::expanduser;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_REGISTRY_NAME: &str = "hyprland-community";
+pub const DEFAULT_REGISTRY_URL: &str = "https://github.com/hyprland-community/theme-repo/blob/main/themes.json?raw=true";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Registry {
+    pub name: String,
+    pub url: String,
+}
+
+impl Registry {
+    pub fn new(name: &str, url: &str) -> Registry {
+        Registry {
+            name: name.to_string(),
+            url: url.to_string(),
+        }
+    }
+}
+
+fn default_registries() -> Vec<Registry> {
+    vec![Registry::new(DEFAULT_REGISTRY_NAME, DEFAULT_REGISTRY_URL)]
+}
+
+// whose cleanup hooks run when themes are disabled or switched away from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Cleanup {
+    // only the themes going away
+    #[default]
+    Outgoing,
+    // every theme that was enabled before the change
+    All,
+    Never,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    // when printing to a terminal and $NO_COLOR isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+pub fn config_home() -> PathBuf {
+    match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => expanduser("~/.config").unwrap(),
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn expand(path: &str) -> PathBuf {
+    PathBuf::from(shellexpand::tilde(path).as_ref())
+}
+
+// ~/.config/hyprtheme/config.toml, every field can be left out
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Settings {
+    // where themes are installed, $XDG_CONFIG_HOME/hypr/themes when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme_dir: Option<String>,
+
+    // the config hyprtheme manages and hyprland.conf sources, <theme_dir>/hyprtheme.conf when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<String>,
+
+    #[serde(default = "default_registries")]
+    pub registries: Vec<Registry>,
+
+    // theme directories hyprtheme:// links may use besides theme_dir
+    #[serde(default)]
+    pub theme_roots: Vec<String>,
+
+    // reload hyprland after every change to the managed config
+    #[serde(default = "default_true")]
+    pub auto_reload: bool,
+
+    #[serde(default)]
+    pub cleanup: Cleanup,
+
+    #[serde(default)]
+    pub color: Color,
+
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings {
+            theme_dir: None,
+            config: None,
+            registries: default_registries(),
+            theme_roots: Vec::new(),
+            auto_reload: true,
+            cleanup: Cleanup::default(),
+            color: Color::default(),
+            path: Settings::default_path(),
+        }
+    }
+
+    pub fn default_path() -> PathBuf {
+        config_home().join("hyprtheme").join("config.toml")
+    }
+
+    pub fn load(path: Option<PathBuf>) -> Result<Settings, String> {
+        Settings::from(path.map(|path| expand(&path.to_string_lossy())).unwrap_or(Settings::default_path()))
+    }
+
+    pub fn theme_dir(&self) -> PathBuf {
+        match &self.theme_dir {
+            Some(theme_dir) => expand(theme_dir),
+            None => config_home().join("hypr").join("themes"),
+        }
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        match &self.config {
+            Some(config) => expand(config),
+            None => self.theme_dir().join("hyprtheme.conf"),
+        }
+    }
+
+    // theme_dir first, it's what links use when they don't name a directory
+    pub fn theme_roots(&self) -> Vec<PathBuf> {
+        let mut roots = vec![self.theme_dir()];
+        roots.extend(self.theme_roots.iter().map(|root| expand(root)));
+        roots
+    }
+
+    // asked once for stdout and once for stderr
+    pub fn use_color(&self, stream: &impl IsTerminal) -> bool {
+        match self.color {
+            Color::Always => true,
+            Color::Never => false,
+            Color::Auto => stream.is_terminal() && std::env::var("NO_COLOR").unwrap_or_default().is_empty(),
+        }
+    }
+
+    // --theme-dir and --config given to a single command, a moved theme dir takes the default config with it
+    pub fn override_paths(&mut self, theme_dir: Option<PathBuf>, config: Option<PathBuf>) {
+        if let Some(theme_dir) = theme_dir {
+            self.theme_dir = Some(theme_dir.display().to_string());
+        }
+        if let Some(config) = config {
+            self.config = Some(config.display().to_string());
+        }
+    }
+
+    pub fn from(path: PathBuf) -> Result<Settings, String> {
+        if !path.exists() {
+            let mut settings = Settings::new();
+            settings.path = path;
+            return Ok(settings);
+        }
+
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        match toml::from_str::<Settings>(&content) {
+            Ok(mut settings) => {
+                settings.path = path;
+                Ok(settings)
+            },
+            Err(e) => Err(format!("Failed to parse {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                return Err(format!("Failed to create {}: {}", parent.display(), e));
+            }
+        }
+
+        let content = match toml::to_string_pretty(self) {
+            Ok(content) => content,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        match std::fs::write(&self.path, content) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Failed to write to {}: {}", self.path.display(), e)),
+        }
+    }
+
+    pub fn add_registry(&mut self, registry: Registry) -> Result<(), String> {
+        if registry.name.contains('/') {
+            return Err(format!("Registry name {} may not contain '/'", registry.name));
+        }
+        for r in &self.registries {
+            if r.name == registry.name {
+                return Err(format!("Registry {} already exists", registry.name));
+            }
+            if r.url == registry.url {
+                return Err(format!("Registry {} already uses {}", r.name, registry.url));
+            }
+        }
+        self.registries.push(registry);
+        Ok(())
+    }
+
+    pub fn remove_registry(&mut self, registry: &str) -> Result<Registry, String> {
+        match self.registries.iter().position(|r| r.name == registry || r.url == registry) {
+            Some(index) => Ok(self.registries.remove(index)),
+            None => Err(format!("Registry {} not found", registry)),
+        }
+    }
+}
@@ -1,15 +1,37 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
+use crate::say;
 use crate::util::ansi::{green, reset, bold};
+use crate::util::config::Module;
+use crate::util::git::{self, PullResult};
+use crate::util::lock::{LockedTheme, Lockfile};
+use crate::util::output;
+
+use git2::Repository;
 
-use expanduser::expanduser;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Themes {
     pub themes: Vec<Theme>,
 }
 
+#[derive(Serialize, Debug, Clone)]
+pub struct Commit {
+    pub hash: String,
+    pub summary: String,
+}
+
+// what `update` found upstream, `applied` is false for dry runs and themes already up to date
+#[derive(Serialize, Debug, Clone)]
+pub struct Update {
+    pub from: String,
+    pub to: String,
+    pub added: Vec<Commit>,
+    pub dropped: Vec<Commit>,
+    pub applied: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Theme {
     pub name: String,
@@ -18,34 +40,93 @@ pub struct Theme {
     pub config: String,
     pub desc: String,
     pub images: Vec<String>,
-    pub _installed: Option<bool>
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub _installed: Option<bool>,
+    pub _registry: Option<String>,
+}
+
+// a theme's directory name, anything that could leave the theme dir is refused
+pub fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\'])
+        && !name.contains("..");
+    match valid {
+        true => Ok(()),
+        false => Err(format!("Invalid theme name {:?}", name)),
+    }
 }
 
 impl Theme {
+    pub fn new(name: &str, repo: &str, branch: &str) -> Theme {
+        Theme {
+            name: name.to_string(),
+            repo: repo.to_string(),
+            branch: branch.to_string(),
+            config: String::from("theme.conf"),
+            desc: String::new(),
+            images: Vec::new(),
+            tags: Vec::new(),
+            _installed: None,
+            _registry: None,
+        }
+    }
+
+    // the standardized name the theme is installed under
+    fn dir_name(&self) -> Result<String, String> {
+        let theme_name = self.name.to_lowercase().replace(" ", "_");
+        check_name(&theme_name)?;
+        Ok(theme_name)
+    }
+
     pub fn get_author(&self) -> String {
-        let mut split = self.repo.split('/').collect::<Vec<&str>>();
+        let mut split = self.repo.trim_end_matches('/').split('/').collect::<Vec<&str>>();
         split.reverse();
-        split[1].to_string()
+        match split.get(1) {
+            Some(author) => author.to_string(),
+            None => String::from("unknown"),
+        }
+    }
+
+    pub fn qualified_name(&self) -> String {
+        match &self._registry {
+            Some(registry) => format!("{}/{}", registry, self.name),
+            None => self.name.clone(),
+        }
     }
 
-    pub async fn fetch_preview(&self) -> Result<Vec<u8>, String> {
-        if self.images.len() == 0 {
-            return Err("No preview images found".to_string());
+    // images can be urls or paths, relative ones are looked up inside the installed theme
+    pub async fn fetch_preview(&self, install_dir: &Path) -> Result<Vec<u8>, String> {
+        let image = match self.images.first() {
+            Some(image) => image,
+            None => return Err("No preview images found".to_string()),
+        };
+        if !image.contains("://") || image.starts_with("file://") {
+            let path = PathBuf::from(shellexpand::tilde(image.trim_start_matches("file://")).as_ref());
+            let path = match path.is_relative() {
+                true => install_dir.join(self.name.to_lowercase().replace(" ", "_")).join(path),
+                false => path,
+            };
+            return std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e));
         }
-        match reqwest::get(&self.images[0]).await {
-            Ok(res) => match res.bytes().await {
-                Ok(bytes) => Ok(bytes.to_vec()),
+        match reqwest::get(image).await {
+            Ok(res) => match res.error_for_status() {
+                Ok(res) => match res.bytes().await {
+                    Ok(bytes) => Ok(bytes.to_vec()),
+                    Err(e) => Err(e.to_string()),
+                },
                 Err(e) => Err(e.to_string()),
             },
             Err(e) => Err(e.to_string()),
         }
     }
 
-    pub fn install(&self, install_dir: Option<PathBuf>) -> Result<(), String>{
-        let install_dir = install_dir.unwrap_or(expanduser("~/.config/hypr/themes").unwrap());
+    // `run_hooks` has to be asked for, the install hook comes straight from the theme's repository
+    pub fn install(&self, install_dir: PathBuf, rev: Option<&str>, force: bool, run_hooks: bool) -> Result<(), String>{
 
         //standardize theme name
-        let theme_name = self.name.to_lowercase().replace(" ", "_");
+        let theme_name = self.dir_name()?;
 
         let theme_dir = install_dir.join(&theme_name);
 
@@ -54,27 +135,77 @@ impl Theme {
             return Err(format!("Theme {} is already installed", &self.name));
         }
 
-        println!("Installing theme {} to {}\n", &self.name, theme_dir.to_str().unwrap());
-        // clone repo
-        let clone_cmd = format!("git clone --depth 1 --branch {} {} {}", &self.branch, &self.repo, theme_dir.to_str().unwrap());
-        
-        match std::process::Command::new("sh")
-        .arg("-c")
-        .stdout(std::process::Stdio::inherit())
-        .stderr(std::process::Stdio::inherit())
-        .arg(&clone_cmd)
-        .output() {
-            Ok(_) => Ok(()),
-            Err(e) => return Err(e.to_string()),
+        say!("Installing theme {} to {}\n", &self.name, theme_dir.to_str().unwrap());
+
+        let source = Path::new(&self.repo);
+        if source.is_dir() && !source.join(".git").exists() {
+            if let Some(rev) = rev {
+                return Err(format!("Can't check out {} of {}, it is not a git repository", rev, source.display()));
+            }
+            // a plain directory, e.g. a theme that's still being developed
+            let mut options = fs_extra::dir::CopyOptions::new();
+            options.content_only = true;
+            if let Err(e) = std::fs::create_dir_all(&theme_dir) {
+                return Err(e.to_string());
+            }
+            if let Err(e) = fs_extra::dir::copy(source, &theme_dir, &options) {
+                let _ = std::fs::remove_dir_all(&theme_dir);
+                return Err(e.to_string());
+            }
+            Theme::check_installed(&theme_dir, force, run_hooks)?;
+            self.lock(&install_dir, &theme_name, None, None);
+            return Ok(());
         }
 
+        // clone repo, then move to the requested tag or commit
+        let cloned = git::clone(&self.repo, &self.branch, &theme_dir, rev.is_none()).and_then(|repository| {
+            if let Some(rev) = rev {
+                let incoming = git::incoming(&repository, Some(rev), true)?;
+                git::apply(&repository, &incoming)?;
+            }
+            Ok(repository)
+        });
+
+        match cloned {
+            Ok(repository) => {
+                Theme::check_installed(&theme_dir, force, run_hooks)?;
+                self.lock(&install_dir, &theme_name, Some(&repository), rev);
+                Ok(())
+            },
+            Err(e) => {
+                // don't leave a half cloned theme behind, it would count as installed
+                if theme_dir.exists() {
+                    let _ = std::fs::remove_dir_all(&theme_dir);
+                }
+                Err(e.to_string())
+            },
+        }
+    }
+
+    // dependencies are declared inside the theme, so they can only be checked once it's on disk
+    fn check_installed(theme_dir: &Path, force: bool, run_hooks: bool) -> Result<(), String> {
+        let module = Module::new(None, theme_dir.to_path_buf());
+        let hook = module.check_dependencies(force).and_then(|_| match (run_hooks, module.hook_script("install")?) {
+            (true, _) => module.run_hook("install"),
+            (false, Some(script)) => {
+                output::warn(format!("Not running the install hook {}, install with --run-hooks to run it", script.display()));
+                Ok(())
+            },
+            (false, None) => Ok(()),
+        });
+        match hook {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(theme_dir);
+                Err(e)
+            },
+        }
     }
 
-    pub fn uninstall(&self, install_dir: Option<PathBuf>) -> Result<(),String> {
-        let install_dir = install_dir.unwrap_or(expanduser("~/.config/hypr/themes").unwrap());
+    pub fn uninstall(&self, install_dir: PathBuf) -> Result<(),String> {
 
         //standardize theme name
-        let theme_name = self.name.to_lowercase().replace(" ", "_");
+        let theme_name = self.dir_name()?;
 
         let theme_dir = install_dir.join(&theme_name);
 
@@ -83,20 +214,22 @@ impl Theme {
             return Err(format!("Theme {} is not installed", &self.name));
         }
 
-        println!("Uninstalling theme {} from {}", &self.name, theme_dir.to_str().unwrap());
+        say!("Uninstalling theme {} from {}", &self.name, theme_dir.to_str().unwrap());
         
         // delete dir
         match std::fs::remove_dir_all(theme_dir) {
-            Ok(_) => Ok(()),
+            Ok(_) => {
+                self.unlock(&install_dir, &theme_name);
+                Ok(())
+            },
             Err(e) => Err(e.to_string()),
         }
     }
 
-    pub fn update(&self, install_dir: Option<PathBuf>) -> Result<(),String> {
-        let install_dir = install_dir.unwrap_or(expanduser("~/.config/hypr/themes").unwrap());
+    pub fn update(&self, install_dir: PathBuf, to: Option<&str>, dry_run: bool) -> Result<Update,String> {
 
         //standardize theme name
-        let theme_name = self.name.to_lowercase().replace(" ", "_");
+        let theme_name = self.dir_name()?;
 
         let theme_dir = install_dir.join(&theme_name);
 
@@ -105,18 +238,113 @@ impl Theme {
             return Err(format!("Theme {} is not installed", &self.name));
         }
 
-        println!("Updating theme {} in {}", &self.name, theme_dir.to_str().unwrap());
-        
-        // delete dir
-        match std::process::Command::new("sh")
-        .arg("-c")
-        .stdout(std::process::Stdio::inherit())
-        .stderr(std::process::Stdio::inherit())
-        .current_dir(&theme_dir)
-        .arg("git pull")
-        .output() {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e.to_string()),
+        // pinned themes only move when asked to
+        let pinned = Lockfile::load(&install_dir).ok()
+            .and_then(|lockfile| lockfile.get(&theme_name).and_then(|locked| locked.rev.clone()));
+        if let (Some(pinned), None) = (&pinned, to) {
+            return Err(format!("Theme {} is pinned to {}, move it with --to <rev>", &self.name, pinned));
+        }
+
+        say!("Updating theme {} in {}", &self.name, theme_dir.to_str().unwrap());
+
+        let repository = git::open(&theme_dir).map_err(|e| e.to_string())?;
+        let incoming = git::incoming(&repository, to, true).map_err(|e| e.to_string())?;
+
+        let added = git::log(&repository, incoming.from, incoming.to).map_err(|e| e.to_string())?;
+        let dropped = git::log(&repository, incoming.to, incoming.from).map_err(|e| e.to_string())?;
+        let mut update = Update {
+            from: incoming.from.to_string(),
+            to: incoming.to.to_string(),
+            added: added.into_iter().map(|(hash, summary)| Commit { hash, summary }).collect(),
+            dropped: dropped.into_iter().map(|(hash, summary)| Commit { hash, summary }).collect(),
+            applied: false,
+        };
+        if dry_run {
+            return Ok(update);
+        }
+
+        match git::apply(&repository, &incoming) {
+            Ok(PullResult::UpToDate) => {},
+            Ok(PullResult::Updated { .. }) => update.applied = true,
+            Err(e) => return Err(e.to_string()),
+        }
+
+        // moving to a branch unpins the theme again
+        let rev = match incoming.branch {
+            Some(_) => None,
+            None => to,
+        };
+        self.lock(&install_dir, &theme_name, Some(&repository), rev);
+        Ok(update)
+    }
+
+    // how many commits `update` would bring in, pinned and copied themes never have any,
+    // `offline` only counts what the last fetch brought in
+    pub fn pending_updates(&self, install_dir: &Path, offline: bool) -> Result<usize, String> {
+        let theme_name = self.name.to_lowercase().replace(" ", "_");
+        let theme_dir = install_dir.join(&theme_name);
+
+        let pinned = Lockfile::load(install_dir).ok()
+            .is_some_and(|lockfile| lockfile.get(&theme_name).is_some_and(|locked| locked.rev.is_some()));
+        if pinned || !theme_dir.join(".git").exists() {
+            return Ok(0);
+        }
+
+        let repository = git::open(&theme_dir).map_err(|e| e.to_string())?;
+        let incoming = git::incoming(&repository, None, !offline).map_err(|e| e.to_string())?;
+        let added = git::log(&repository, incoming.from, incoming.to).map_err(|e| e.to_string())?;
+        Ok(added.len())
+    }
+
+    // record where an installed theme came from and which commit it is at in hyprtheme.lock
+    fn lock(&self, install_dir: &Path, theme_name: &str, repository: Option<&Repository>, rev: Option<&str>) {
+        let mut lockfile = match Lockfile::load(install_dir) {
+            Ok(lockfile) => lockfile,
+            Err(e) => return output::warn(e),
+        };
+
+        let previous = lockfile.remove(theme_name);
+        let registry = self._registry.clone().or(previous.as_ref().and_then(|locked| locked.registry.clone()));
+        // a pinned checkout is detached, remember the branch it came from
+        let branch = match &previous {
+            Some(locked) if !locked.branch.is_empty() => locked.branch.clone(),
+            _ => self.branch.clone(),
+        };
+
+        let locked = match repository {
+            Some(repository) => LockedTheme {
+                name: theme_name.to_string(),
+                registry,
+                repo: git::remote_url(repository).unwrap_or(self.repo.clone()),
+                branch: git::head_branch(repository).unwrap_or(branch),
+                commit: git::head_commit(repository).ok(),
+                rev: rev.map(|rev| rev.to_string()),
+            },
+            None => LockedTheme {
+                name: theme_name.to_string(),
+                registry,
+                repo: self.repo.clone(),
+                branch,
+                commit: None,
+                rev: None,
+            },
+        };
+        lockfile.insert(locked);
+
+        if let Err(e) = lockfile.save() {
+            output::warn(e);
+        }
+    }
+
+    fn unlock(&self, install_dir: &Path, theme_name: &str) {
+        let mut lockfile = match Lockfile::load(install_dir) {
+            Ok(lockfile) => lockfile,
+            Err(e) => return output::warn(e),
+        };
+        if lockfile.remove(theme_name).is_some() {
+            if let Err(e) = lockfile.save() {
+                output::warn(e);
+            }
         }
     }
 }
@@ -0,0 +1,281 @@
+use std::path::{Path, PathBuf};
+
+pub const SCHEME: &str = "hyprtheme:";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UriAction {
+    Install,
+    Uninstall,
+    Enable,
+    Switch,
+    Update,
+    Info,
+    // install and enable in one go, for "try this theme" buttons
+    Try,
+}
+
+impl UriAction {
+    fn from_name(name: &str) -> Option<UriAction> {
+        match name.to_lowercase().as_str() {
+            "install" => Some(UriAction::Install),
+            "uninstall" => Some(UriAction::Uninstall),
+            "enable" => Some(UriAction::Enable),
+            "switch" => Some(UriAction::Switch),
+            "update" => Some(UriAction::Update),
+            "info" | "preview" => Some(UriAction::Info),
+            "try" => Some(UriAction::Try),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UriAction::Install => "install",
+            UriAction::Uninstall => "uninstall",
+            UriAction::Enable => "enable",
+            UriAction::Switch => "switch",
+            UriAction::Update => "update",
+            UriAction::Info => "info",
+            UriAction::Try => "try",
+        }
+    }
+}
+
+// a `hyprtheme://` link, either `<action>/<theme>?theme_dir=..&rev=..` or the older `<action>+<theme>[+<theme_dir>]`
+#[derive(Debug, Clone)]
+pub struct ThemeUri {
+    pub action: UriAction,
+    pub theme: String,
+    pub theme_dir: Option<String>,
+    pub rev: Option<String>,
+}
+
+// `%XX` escapes, `+` only means a space inside the query
+fn percent_decode(value: &str, plus_is_space: bool) -> Result<String, String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = bytes.get(i + 1..i + 3)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match hex {
+                    Some(byte) => decoded.push(byte),
+                    None => return Err(format!("Invalid escape in {}", value)),
+                }
+                i += 3;
+            },
+            b'+' if plus_is_space => {
+                decoded.push(b' ');
+                i += 1;
+            },
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+    match String::from_utf8(decoded) {
+        Ok(decoded) if !decoded.chars().any(|c| c.is_control()) => Ok(decoded),
+        _ => Err(format!("Invalid characters in {}", value)),
+    }
+}
+
+// registry names only, `<registry>/name` at most, links can't point at git urls or local paths
+fn check_theme(theme: &str) -> Result<(), String> {
+    let valid = !theme.is_empty()
+        && theme.len() <= 100
+        && !theme.starts_with(['.', '/', ' '])
+        && !theme.contains("..")
+        && theme.matches('/').count() <= 1
+        && theme.chars().all(|c| c.is_alphanumeric() || [' ', '-', '_', '.', '/'].contains(&c));
+    match valid {
+        true => Ok(()),
+        false => Err(format!("Invalid theme name {:?}", theme)),
+    }
+}
+
+fn check_rev(rev: &str) -> Result<(), String> {
+    let valid = !rev.is_empty()
+        && rev.len() <= 64
+        && !rev.starts_with(['.', '-'])
+        && rev.chars().all(|c| c.is_ascii_alphanumeric() || ['-', '_', '.'].contains(&c));
+    match valid {
+        true => Ok(()),
+        false => Err(format!("Invalid revision {:?}", rev)),
+    }
+}
+
+pub fn parse(uri: &str) -> Result<ThemeUri, String> {
+    let rest = match uri.get(..SCHEME.len()) {
+        Some(scheme) if scheme.eq_ignore_ascii_case(SCHEME) => &uri[SCHEME.len()..],
+        _ => return Err(format!("{} is not a hyprtheme:// link", uri)),
+    };
+    let rest = rest.trim_start_matches('/');
+    let rest = rest.split_once('#').map(|(rest, _)| rest).unwrap_or(rest);
+    let (path, query) = match rest.split_once('?') {
+        Some((path, query)) => (path.trim_end_matches('/'), Some(query)),
+        None => (rest.trim_end_matches('/'), None),
+    };
+
+    let (action, theme, mut theme_dir) = match path.find(['+', '/']) {
+        Some(index) if path[index..].starts_with('+') => {
+            let mut fields = path.split('+');
+            let action = fields.next().unwrap_or_default();
+            let theme = fields.next().unwrap_or_default();
+            let theme_dir = fields.next().map(|dir| percent_decode(dir, false)).transpose()?;
+            if fields.next().is_some() {
+                return Err(format!("Too many fields in {}", uri));
+            }
+            (action, theme, theme_dir)
+        },
+        Some(index) => (&path[..index], &path[index + 1..], None),
+        None => return Err(format!("{} doesn't name a theme", uri)),
+    };
+
+    let action = match UriAction::from_name(action) {
+        Some(action) => action,
+        None => return Err(format!("Unknown action {:?}", action)),
+    };
+    let theme = percent_decode(theme, false)?;
+    check_theme(&theme)?;
+
+    let mut rev = None;
+    for pair in query.unwrap_or_default().split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value, true)?;
+        let slot = match percent_decode(key, true)?.as_str() {
+            "theme_dir" => &mut theme_dir,
+            "rev" => {
+                check_rev(&value)?;
+                &mut rev
+            },
+            key => return Err(format!("Unknown parameter {:?}", key)),
+        };
+        if slot.replace(value).is_some() {
+            return Err(format!("Parameter {:?} given more than once", key));
+        }
+    }
+
+    Ok(ThemeUri { action, theme, theme_dir, rev })
+}
+
+// links may only touch the theme directories listed in the settings, the first one is the default
+pub fn resolve_theme_dir(theme_dir: Option<&str>, roots: &[PathBuf]) -> Result<PathBuf, String> {
+    let canonical = |path: &Path| path.canonicalize().ok();
+    let roots = roots.iter().filter_map(|root| canonical(root)).collect::<Vec<PathBuf>>();
+
+    let theme_dir = match theme_dir {
+        Some(theme_dir) => PathBuf::from(shellexpand::tilde(theme_dir).as_ref()),
+        None => match roots.first() {
+            Some(root) => return Ok(root.clone()),
+            None => return Err("None of the configured theme directories exist".to_string()),
+        },
+    };
+    if !theme_dir.is_absolute() {
+        return Err(format!("Theme directory {} has to be an absolute path", theme_dir.display()));
+    }
+    match canonical(&theme_dir) {
+        Some(theme_dir) if roots.contains(&theme_dir) => Ok(theme_dir),
+        _ => Err(format!("{} is not one of the configured theme directories", theme_dir.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejected(link: &str) {
+        assert!(parse(link).is_err(), "{} was accepted", link);
+    }
+
+    #[test]
+    fn links_are_parsed() {
+        let link = parse("hyprtheme://install/community/nord?rev=v1.0&theme_dir=%2Ftmp%2Fthemes").unwrap();
+        assert_eq!(link.action, UriAction::Install);
+        assert_eq!(link.theme, "community/nord");
+        assert_eq!(link.rev.as_deref(), Some("v1.0"));
+        assert_eq!(link.theme_dir.as_deref(), Some("/tmp/themes"));
+
+        let link = parse("hyprtheme://enable+Catppuccin%20Mocha+/tmp/themes/").unwrap();
+        assert_eq!(link.action, UriAction::Enable);
+        assert_eq!(link.theme, "Catppuccin Mocha");
+        assert_eq!(link.theme_dir.as_deref(), Some("/tmp/themes"));
+    }
+
+    #[test]
+    fn theme_names_stay_registry_names() {
+        for link in [
+            "hyprtheme://install/..",
+            "hyprtheme://install/%2E%2E",
+            "hyprtheme://install/nord%2F..%2F..%2Fetc",
+            "hyprtheme://install/%2Fetc",
+            "hyprtheme://install/community//nord",
+            "hyprtheme://install/community%2F%2Fnord",
+            "hyprtheme://install/a/b/c",
+            "hyprtheme://install/nord%0A",
+            "hyprtheme://install/nord%00",
+            "hyprtheme://install/nord%1B%5B31m",
+            "hyprtheme://install/nord%ZZ",
+            "hyprtheme://install/https:%2F%2Fgithub.com%2Fx",
+            "hyprtheme://install/",
+            "hyprtheme://explode/nord",
+            "https://install/nord",
+        ] {
+            rejected(link);
+        }
+    }
+
+    #[test]
+    fn parameters_are_checked() {
+        for link in [
+            "hyprtheme://install/nord?branch=main",
+            "hyprtheme://install/nord?rev=v1&rev=v2",
+            "hyprtheme://install/nord?theme_dir=/a&theme_dir=/b",
+            "hyprtheme://install+nord+/a?theme_dir=/b",
+            "hyprtheme://install/nord?theme_dir=%2Ftmp%0A",
+            "hyprtheme://install+nord+/a+/b",
+        ] {
+            rejected(link);
+        }
+    }
+
+    #[test]
+    fn revisions_are_checked() {
+        for rev in ["", "-x", "--upload-pack=evil", "..", ".hidden", "a/b", "a%20b", "a+b", "a~1", "a^", &"a".repeat(65)] {
+            rejected(&format!("hyprtheme://update/nord?rev={}", rev));
+        }
+        for rev in ["v1.0", "main", "0123abcd", "release_2"] {
+            assert_eq!(parse(&format!("hyprtheme://update/nord?rev={}", rev)).unwrap().rev.as_deref(), Some(rev));
+        }
+    }
+
+    #[test]
+    fn theme_dirs_stay_in_the_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("themes");
+        let other = dir.path().join("other");
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+        std::os::unix::fs::symlink(&other, dir.path().join("link")).unwrap();
+        let root = root.canonicalize().unwrap();
+        let roots = vec![dir.path().join("missing"), root.clone()];
+
+        assert_eq!(resolve_theme_dir(None, &roots).unwrap(), root);
+        assert_eq!(resolve_theme_dir(Some(&format!("{}/sub/..", root.display())), &roots).unwrap(), root);
+        for theme_dir in [
+            other.display().to_string(),
+            dir.path().join("link").display().to_string(),
+            root.join("sub").display().to_string(),
+            format!("{}/..", root.display()),
+            dir.path().join("missing").display().to_string(),
+            String::from("themes"),
+            String::from("/"),
+        ] {
+            assert!(resolve_theme_dir(Some(&theme_dir), &roots).is_err(), "{} was accepted", theme_dir);
+        }
+        assert!(resolve_theme_dir(None, &[dir.path().join("missing")]).is_err());
+    }
+}